@@ -15,7 +15,7 @@ pub fn setup_context(current: &str, expr: &str) -> (Context, Expr) {
         .expect("expected single cell position");
     if let Expr::Ref(Ref::CellRef(x, y)) = current {
         let mut ctx = Context {
-            current_loc: Some((x, y)),
+            current_loc: Some((x.id, y.id)),
             ..Default::default()
         };
 
@@ -26,7 +26,7 @@ pub fn setup_context(current: &str, expr: &str) -> (Context, Expr) {
             Box::new(|_, _| Err(Error::Unimplemented)),
         );
 
-        ctx.sheet.set(
+        ctx.sheet_mut().unwrap().set(
             0,
             0,
             Cell {
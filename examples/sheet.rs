@@ -2,11 +2,12 @@ use open_formula::prelude::*;
 
 fn main() {
     let mut context = Context::default();
+    let sheet = context.sheet_mut().unwrap();
     // Add two cells to our worksheet:
     // A   | B
     // ---------
     // 5.0 | 2.0
-    context.sheet.set(
+    sheet.set(
         0,
         0,
         Cell {
@@ -14,7 +15,7 @@ fn main() {
             expr: None,
         },
     );
-    context.sheet.set(
+    sheet.set(
         1,
         0,
         Cell {
@@ -1,6 +1,7 @@
 use chumsky::Parser;
 use inquire::autocompletion::{Autocomplete, Replacement};
 use inquire::{CustomUserError, Text};
+use open_formula::diagnostics::diagnostics;
 use open_formula::parser::parser;
 use open_formula::sql::transform_with_columns;
 
@@ -20,8 +21,15 @@ impl Autocomplete for Complete {
                 Ok(vec![format!("error: {:?}", sql.unwrap_err())])
             }
         } else if res.has_errors() {
-            // TODO: use ariadne instead
-            Ok(vec![format!("error: {:?}", res.into_errors())])
+            Ok(diagnostics(&res.into_errors())
+                .iter()
+                .flat_map(|d| {
+                    d.render(input)
+                        .lines()
+                        .map(String::from)
+                        .collect::<Vec<_>>()
+                })
+                .collect())
         } else {
             Ok(vec![])
         }
@@ -20,6 +20,14 @@ impl ConvertToScalar for Result<Value> {
         match self {
             Ok(Value::Num(_)) | Ok(Value::Bool(_)) | Ok(Value::String(_)) => self,
             Ok(Value::Ref(r)) => eval_ref(ctx, &r),
+            Ok(Value::Error(e)) => Err(e),
+            // arrays are reduced to their top left element
+            Ok(Value::Array(rows)) => rows
+                .into_iter()
+                .next()
+                .and_then(|row| row.into_iter().next())
+                .ok_or(Error::Value)
+                .convert_to_scalar(ctx),
             Err(_) => self,
             _ => Err(Error::Value),
         }
@@ -45,6 +53,8 @@ impl ConvertToNumber for Result<Value> {
             Ok(Value::EmptyCell) => Ok(Value::Num(0f64)),
             Ok(Value::String(s)) => f64::from_str(&s).map(Value::Num).map_err(|_| Error::Value),
             Ok(Value::Ref(r)) => eval_ref(ctx, &r).convert_to_number(ctx),
+            Ok(Value::Error(e)) => Err(e),
+            Ok(Value::Array(_)) => self.convert_to_scalar(ctx).convert_to_number(ctx),
             Err(_) => self,
         }
     }
@@ -64,6 +74,8 @@ impl ConvertToLogical for Result<Value> {
             Ok(Value::Bool(_)) => self,
             Ok(Value::Ref(_)) => self.convert_to_scalar(ctx).convert_to_logical(ctx),
             Ok(Value::EmptyCell) => Ok(Value::Bool(false)),
+            Ok(Value::Error(e)) => Err(e),
+            Ok(Value::Array(_)) => self.convert_to_scalar(ctx).convert_to_logical(ctx),
             Err(_) => self,
         }
     }
@@ -92,6 +104,8 @@ impl ConvertToText for Result<Value> {
             }
             Ok(Value::Ref(_)) => self.convert_to_scalar(ctx).convert_to_text(ctx),
             Ok(Value::EmptyCell) => Ok(Value::String("".to_string())),
+            Ok(Value::Error(e)) => Err(e),
+            Ok(Value::Array(_)) => self.convert_to_scalar(ctx).convert_to_text(ctx),
             Err(_) => self,
         }
     }
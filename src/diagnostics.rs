@@ -0,0 +1,152 @@
+//! Human readable diagnostics for formula parse errors.
+
+use std::ops::Range;
+
+use chumsky::error::{Rich, RichReason};
+
+/// A structured description of a parse error.
+///
+/// Example
+/// ```rust
+/// use open_formula::{diagnostics::Diagnostic, parser::{Parser, parser}};
+/// let errors = parser().parse("SUM(1;2").into_errors();
+/// let diagnostic = Diagnostic::from(&errors[0]);
+/// assert_eq!(diagnostic.message, "unexpected end of input");
+/// assert_eq!(diagnostic.span, 7..7);
+/// assert!(diagnostic.expected.contains(&"')'".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Description of the problem.
+    pub message: String,
+    /// Byte range of the formula string where the problem was found.
+    pub span: Range<usize>,
+    /// Descriptions of the inputs that would have been valid at this position.
+    pub expected: Vec<String>,
+    /// The character found instead of the expected input, `None` at the end of input.
+    pub found: Option<char>,
+}
+
+impl From<&Rich<'_, char>> for Diagnostic {
+    fn from(err: &Rich<'_, char>) -> Self {
+        let message = match err.reason() {
+            RichReason::Custom(msg) => msg.clone(),
+            RichReason::ExpectedFound { found: None, .. } => "unexpected end of input".into(),
+            RichReason::ExpectedFound { found: Some(c), .. } => {
+                format!("unexpected character '{}'", **c)
+            }
+        };
+        let mut expected: Vec<_> = err.expected().map(|p| p.to_string()).collect();
+        expected.sort();
+        Self {
+            message,
+            span: err.span().into_range(),
+            expected,
+            found: err.found().copied(),
+        }
+    }
+}
+
+impl Diagnostic {
+    /// Returns the text of the label that points at the problem.
+    pub fn label(&self) -> String {
+        match &self.expected[..] {
+            [] => self.message.clone(),
+            [expected] => format!("expected {}", expected),
+            expected => format!(
+                "expected {} or {}",
+                expected[..expected.len() - 1].join(", "),
+                expected[expected.len() - 1]
+            ),
+        }
+    }
+
+    /// Returns a labelled report of the diagnostic.
+    #[cfg(feature = "ariadne")]
+    pub fn report(&self) -> ariadne::Report<'static, Range<usize>> {
+        self.report_builder().finish()
+    }
+
+    /// Renders the diagnostic for the given formula as plain text.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::{diagnostics::Diagnostic, parser::{Parser, parser}};
+    /// let formula = "1+*2";
+    /// let errors = parser().parse(formula).into_errors();
+    /// let text = Diagnostic::from(&errors[0]).render(formula);
+    /// assert!(text.contains("unexpected character '*'"));
+    /// ```
+    #[cfg(feature = "ariadne")]
+    pub fn render(&self, formula: &str) -> String {
+        let mut out = Vec::new();
+        self.report_builder()
+            .with_config(ariadne::Config::default().with_color(false))
+            .finish()
+            .write(ariadne::Source::from(formula), &mut out)
+            .expect("writing into a vector can't fail");
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    #[cfg(feature = "ariadne")]
+    fn report_builder(&self) -> ariadne::ReportBuilder<'static, Range<usize>> {
+        use ariadne::{Label, Report, ReportKind};
+        Report::build(ReportKind::Error, self.span.clone())
+            .with_message(&self.message)
+            .with_label(Label::new(self.span.clone()).with_message(self.label()))
+    }
+}
+
+/// Converts parse errors into diagnostics.
+pub fn diagnostics(errors: &[Rich<'_, char>]) -> Vec<Diagnostic> {
+    errors.iter().map(Diagnostic::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parser, Parser};
+
+    fn check(formula: &str) -> Vec<Diagnostic> {
+        diagnostics(&parser().parse(formula).into_errors())
+    }
+
+    #[test]
+    fn expected_found() {
+        assert_eq!(
+            check("1 2"),
+            vec![Diagnostic {
+                message: "unexpected character '2'".into(),
+                span: 2..3,
+                expected: vec!["end of input".into(), "operator".into()],
+                found: Some('2'),
+            }]
+        );
+        let diagnostic = &check("SUM(1;2")[0];
+        assert_eq!(diagnostic.message, "unexpected end of input");
+        assert_eq!(diagnostic.span, 7..7);
+        assert_eq!(diagnostic.found, None);
+        assert!(diagnostic.expected.contains(&"')'".into()));
+        assert!(diagnostic.expected.contains(&"operator".into()));
+    }
+
+    #[test]
+    fn custom() {
+        let diagnostic = &check("{1;2|3}")[0];
+        assert_eq!(
+            diagnostic.message,
+            "all rows of an inline array must have the same length"
+        );
+        assert!(diagnostic.expected.is_empty());
+        assert_eq!(diagnostic.label(), diagnostic.message);
+    }
+
+    #[cfg(feature = "ariadne")]
+    #[test]
+    fn render() {
+        let text = check("1 2")[0].render("1 2");
+        assert!(text.starts_with("Error: unexpected character '2'"));
+        assert!(text.contains("1 │ 1 2"));
+        assert!(text.contains("expected end of input or operator"));
+    }
+}
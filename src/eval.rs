@@ -1,11 +1,14 @@
 //! An OpenDocument [Formula Evaluator](https://docs.oasis-open.org/office/OpenDocument/v1.4/csd01/part4-formula/OpenDocument-v1.4-csd01-part4-formula.html#__RefHeading__711844_826425813).
 
+use std::cell::RefCell;
+
 use ahash::AHashMap;
 use log::trace;
 
 use crate::{
     conversion::ConvertToNumber,
-    types::{Error, Expr, Ref, Result, Value},
+    functions::canonical_name,
+    types::{Coord, Error, Expr, Ref, Result, SheetName, Value},
 };
 
 /// A single OpenFormula cell.
@@ -22,16 +25,509 @@ type EvalFn = dyn Fn(&[Expr], &Context) -> Result<Value>;
 
 /// A context that is used to evaluate expressions.
 ///
-/// Each evaluation is based on a sheet (which contains cells), an optional
-/// current cursor position and a (possibly empty) list of user defined functions.
+/// Each evaluation is based on a workbook (which contains sheets), the index
+/// of the current sheet, an optional current cursor position and a (possibly
+/// empty) list of user defined functions.
 ///
-/// Currently only single sheet contexts are supported.
-// TODO: this should be expanded into multiple sheets one day (aka Workbook)
+/// References without a sheet name are resolved against the current sheet.
+/// Named expressions are looked up in the defined names of the current sheet
+/// first and then in the names of the workbook. References into other
+/// documents are resolved by the optional external resolver.
 #[derive(Default)]
 pub struct Context {
-    pub sheet: Sheet,
+    pub workbook: Workbook,
+    pub current_sheet: usize,
     pub current_loc: Option<(usize, usize)>,
-    pub functions: AHashMap<String, Box<EvalFn>>,
+    pub functions: Functions,
+    pub names: Names,
+    pub external: Option<Box<dyn ExternalResolver>>,
+}
+
+impl Context {
+    /// Returns the current sheet.
+    pub fn sheet(&self) -> Option<&Sheet> {
+        self.workbook.sheet(self.current_sheet)
+    }
+
+    /// Returns a mutable reference to the current sheet.
+    pub fn sheet_mut(&mut self) -> Option<&mut Sheet> {
+        self.workbook.sheet_mut(self.current_sheet)
+    }
+
+    /// Inserts rows like [`Workbook::insert_rows`] and also adjusts the
+    /// references of the defined names.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::prelude::*;
+    /// let mut ctx = Context::default();
+    /// ctx.names.define("Net", parser().parse("$Sheet1.$A$5").unwrap());
+    /// ctx.insert_rows(0, 0, 2);
+    /// assert_eq!(ctx.names.get(0, "Net").unwrap().to_string(), "$Sheet1.$A$7");
+    /// ```
+    pub fn insert_rows(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Row, Edit::Insert { at, count });
+    }
+
+    /// Deletes rows like [`Workbook::delete_rows`] and also adjusts the
+    /// references of the defined names.
+    pub fn delete_rows(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Row, Edit::Delete { at, count });
+    }
+
+    /// Inserts columns like [`Workbook::insert_columns`] and also adjusts the
+    /// references of the defined names.
+    pub fn insert_columns(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Column, Edit::Insert { at, count });
+    }
+
+    /// Deletes columns like [`Workbook::delete_columns`] and also adjusts the
+    /// references of the defined names.
+    pub fn delete_columns(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Column, Edit::Delete { at, count });
+    }
+
+    fn edit(&mut self, sheet: usize, axis: Axis, edit: Edit) {
+        let Some(target) = self.workbook.sheet_name(sheet).map(str::to_lowercase) else {
+            return;
+        };
+        self.workbook.edit(sheet, axis, edit);
+        // names of the workbook can be used on any sheet, so only their
+        // references with a sheet name point into the edited sheet
+        for expr in self.names.workbook.values_mut() {
+            *expr = edit.adjust_expr(expr.clone(), false, &target, axis);
+        }
+        for ((idx, _), expr) in self.names.sheets.iter_mut() {
+            *expr = edit.adjust_expr(expr.clone(), *idx == sheet, &target, axis);
+        }
+    }
+}
+
+/// Provides the documents of external references, e.g. `'file:///data/rates.ods'#$Sheet1.A1`.
+///
+/// Host applications implement this trait to supply values from linked
+/// workbooks. References into unknown documents evaluate to `#REF!`.
+pub trait ExternalResolver {
+    /// Returns the workbook of the document with the given IRI.
+    fn workbook(&self, iri: &str) -> Option<&Workbook>;
+}
+
+/// An external resolver for documents that are kept in memory.
+///
+/// Example
+/// ```rust
+/// use open_formula::prelude::*;
+/// use open_formula::eval::MemoryResolver;
+/// let mut rates = Workbook::default();
+/// rates.sheet_mut(0).unwrap().set(0, 0, Cell { value: Some(Value::Num(0.19)), expr: None });
+/// let mut resolver = MemoryResolver::default();
+/// resolver.insert("file:///data/rates.ods", rates);
+/// let ctx = Context {
+///     external: Some(Box::new(resolver)),
+///     ..Default::default()
+/// };
+/// let expr = parser().parse("100*'file:///data/rates.ods'#$Sheet1.A1").unwrap();
+/// assert_eq!(eval(&ctx, &expr), Ok(Value::Num(19.0)));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MemoryResolver {
+    workbooks: AHashMap<String, Workbook>,
+}
+
+impl MemoryResolver {
+    /// Adds the workbook of a document and returns the workbook it replaces.
+    pub fn insert<S: Into<String>>(&mut self, iri: S, workbook: Workbook) -> Option<Workbook> {
+        self.workbooks.insert(iri.into(), workbook)
+    }
+}
+
+impl ExternalResolver for MemoryResolver {
+    fn workbook(&self, iri: &str) -> Option<&Workbook> {
+        self.workbooks.get(iri)
+    }
+}
+
+/// A registry of user defined functions.
+///
+/// Function names are compared case-insensitively. A function can be called
+/// by additional alias names, e.g. the names of another producer.
+///
+/// Example
+/// ```rust
+/// use open_formula::prelude::*;
+/// use open_formula::types::Error;
+/// let mut ctx = Context::default();
+/// ctx.functions.insert("Answer".into(), Box::new(|_, _| Ok(Value::Num(42.0))));
+/// ctx.functions.alias("ORG.EXAMPLE.QUESTION", "ANSWER");
+/// let expr = parser().parse("answer()+org.example.question()").unwrap();
+/// assert_eq!(eval(&ctx, &expr), Ok(Value::Num(84.0)));
+/// ```
+#[derive(Default)]
+pub struct Functions {
+    map: AHashMap<String, Box<EvalFn>>,
+    aliases: AHashMap<String, String>,
+}
+
+impl Functions {
+    /// Adds a function and returns the function it replaces.
+    pub fn insert(&mut self, name: String, f: Box<EvalFn>) -> Option<Box<EvalFn>> {
+        self.map.insert(name.to_uppercase(), f)
+    }
+
+    /// Removes a function and returns it.
+    pub fn remove(&mut self, name: &str) -> Option<Box<EvalFn>> {
+        self.map.remove(&name.to_uppercase())
+    }
+
+    /// Makes the function with the given name (either predefined or user
+    /// defined) callable by an alias name.
+    pub fn alias<S: Into<String>, T: Into<String>>(&mut self, alias: S, name: T) {
+        self.aliases
+            .insert(alias.into().to_uppercase(), name.into().to_uppercase());
+    }
+
+    /// Returns the name a function call resolves to.
+    ///
+    /// User defined aliases take precedence over the standard namespaces and
+    /// aliases of [`canonical_name`].
+    pub fn resolve(&self, name: &str) -> String {
+        match self.aliases.get(&name.to_uppercase()) {
+            Some(name) => name.clone(),
+            None => canonical_name(name),
+        }
+    }
+
+    /// Returns the user defined function that is called by the given name.
+    ///
+    /// A function defined with the exact name, e.g. `COM.MICROSOFT.CEILING`,
+    /// is preferred over the function of the resolved name.
+    pub fn get(&self, name: &str) -> Option<&EvalFn> {
+        self.map
+            .get(&name.to_uppercase())
+            .or_else(|| self.map.get(&self.resolve(name)))
+            .map(|f| f.as_ref())
+    }
+}
+
+/// A table of defined names.
+///
+/// Each name maps to an expression, usually a reference like `$Sheet1.$A$1`.
+/// Names are either defined for the whole workbook or only for a single sheet,
+/// where they hide a workbook name of the same name. Names are compared
+/// case-insensitively.
+///
+/// Example
+/// ```rust
+/// use open_formula::prelude::*;
+/// use open_formula::{eval::Names, types::Ref};
+/// let mut names = Names::default();
+/// names.define("TaxRate", Expr::Num(0.19));
+/// names.define_local(0, "TaxRate", Expr::Ref(Ref::cell(0, 0)));
+/// assert_eq!(names.get(0, "taxrate"), Some(&Expr::Ref(Ref::cell(0, 0))));
+/// assert_eq!(names.get(1, "TAXRATE"), Some(&Expr::Num(0.19)));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Names {
+    workbook: AHashMap<String, Expr>,
+    sheets: AHashMap<(usize, String), Expr>,
+    // names that are currently evaluated, to detect circular definitions
+    resolving: RefCell<Vec<(usize, String)>>,
+}
+
+impl Names {
+    /// Defines a name for the whole workbook and returns the replaced definition.
+    pub fn define<S: Into<String>>(&mut self, name: S, expr: Expr) -> Option<Expr> {
+        self.workbook.insert(name.into().to_lowercase(), expr)
+    }
+
+    /// Defines a name for the sheet with the given index and returns the
+    /// replaced definition.
+    pub fn define_local<S: Into<String>>(
+        &mut self,
+        sheet: usize,
+        name: S,
+        expr: Expr,
+    ) -> Option<Expr> {
+        self.sheets
+            .insert((sheet, name.into().to_lowercase()), expr)
+    }
+
+    /// Removes a workbook name and returns its definition.
+    pub fn undefine(&mut self, name: &str) -> Option<Expr> {
+        self.workbook.remove(&name.to_lowercase())
+    }
+
+    /// Removes a name of the sheet with the given index and returns its definition.
+    pub fn undefine_local(&mut self, sheet: usize, name: &str) -> Option<Expr> {
+        self.sheets.remove(&(sheet, name.to_lowercase()))
+    }
+
+    /// Returns the definition of a name as seen from the sheet with the given index.
+    pub fn get(&self, sheet: usize, name: &str) -> Option<&Expr> {
+        let name = name.to_lowercase();
+        self.sheets
+            .get(&(sheet, name.clone()))
+            .or_else(|| self.workbook.get(&name))
+    }
+}
+
+/// An OpenFormula workbook.
+///
+/// A workbook owns an ordered collection of named sheets. The default
+/// workbook contains a single empty sheet named `Sheet1`.
+#[derive(Debug, Clone)]
+pub struct Workbook {
+    sheets: Vec<(String, Sheet)>,
+}
+
+impl Default for Workbook {
+    fn default() -> Self {
+        Self {
+            sheets: vec![("Sheet1".into(), Sheet::default())],
+        }
+    }
+}
+
+impl Workbook {
+    /// Returns a workbook without any sheets.
+    pub fn empty() -> Self {
+        Self { sheets: vec![] }
+    }
+
+    /// Returns the number of sheets.
+    pub fn len(&self) -> usize {
+        self.sheets.len()
+    }
+
+    /// Returns true if the workbook contains no sheets.
+    pub fn is_empty(&self) -> bool {
+        self.sheets.is_empty()
+    }
+
+    /// Adds a sheet with the given name and returns its index.
+    ///
+    /// An existing sheet with the same name is replaced.
+    pub fn add_sheet<S: Into<String>>(&mut self, name: S, sheet: Sheet) -> usize {
+        let name = name.into();
+        if let Some(idx) = self.sheet_index(&name) {
+            self.sheets[idx].1 = sheet;
+            idx
+        } else {
+            self.sheets.push((name, sheet));
+            self.sheets.len() - 1
+        }
+    }
+
+    /// Returns the index of the sheet with the given name.
+    ///
+    /// Sheet names are compared case-insensitively.
+    pub fn sheet_index(&self, name: &str) -> Option<usize> {
+        let name = name.to_lowercase();
+        self.sheets
+            .iter()
+            .position(|(n, _)| n.to_lowercase() == name)
+    }
+
+    /// Returns the name of the sheet at the given index.
+    pub fn sheet_name(&self, idx: usize) -> Option<&str> {
+        self.sheets.get(idx).map(|(n, _)| n.as_str())
+    }
+
+    /// Returns the sheet at the given index.
+    pub fn sheet(&self, idx: usize) -> Option<&Sheet> {
+        self.sheets.get(idx).map(|(_, s)| s)
+    }
+
+    /// Returns a mutable reference to the sheet at the given index.
+    pub fn sheet_mut(&mut self, idx: usize) -> Option<&mut Sheet> {
+        self.sheets.get_mut(idx).map(|(_, s)| s)
+    }
+
+    /// Returns the sheet with the given name.
+    pub fn sheet_by_name(&self, name: &str) -> Option<&Sheet> {
+        self.sheet_index(name).and_then(|idx| self.sheet(idx))
+    }
+
+    /// Returns an iterator over all sheets and their names in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Sheet)> {
+        self.sheets.iter().map(|(n, s)| (n.as_str(), s))
+    }
+
+    /// Inserts `count` empty rows before the row `at` of the sheet with the
+    /// given index.
+    ///
+    /// Cells below move down and all references of the workbook's formulas to
+    /// the moved cells are adjusted, ranges spanning the new rows grow. Use
+    /// [`Context::insert_rows`] to adjust defined names as well.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::prelude::*;
+    /// let mut workbook = Workbook::default();
+    /// let formula = parser().parse("SUM(A1:A5)+A6").unwrap();
+    /// let cell = Cell { value: None, expr: Some(formula) };
+    /// workbook.sheet_mut(0).unwrap().set(0, 6, cell);
+    /// workbook.insert_rows(0, 4, 2);
+    /// let cell = workbook.sheet(0).unwrap().get(0, 8).unwrap();
+    /// assert_eq!(cell.expr.as_ref().unwrap().to_string(), "SUM(A1:A7)+A8");
+    /// ```
+    pub fn insert_rows(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Row, Edit::Insert { at, count });
+    }
+
+    /// Deletes `count` rows starting at the row `at` of the sheet with the
+    /// given index.
+    ///
+    /// Cells below move up and all references of the workbook's formulas to
+    /// the moved cells are adjusted. Ranges that overlap the deleted rows
+    /// shrink, references to deleted cells become `#REF!`. Use
+    /// [`Context::delete_rows`] to adjust defined names as well.
+    pub fn delete_rows(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Row, Edit::Delete { at, count });
+    }
+
+    /// Inserts `count` empty columns before the column `at` of the sheet with
+    /// the given index.
+    ///
+    /// Like [`Workbook::insert_rows`] for columns.
+    pub fn insert_columns(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Column, Edit::Insert { at, count });
+    }
+
+    /// Deletes `count` columns starting at the column `at` of the sheet with
+    /// the given index.
+    ///
+    /// Like [`Workbook::delete_rows`] for columns.
+    pub fn delete_columns(&mut self, sheet: usize, at: usize, count: usize) {
+        self.edit(sheet, Axis::Column, Edit::Delete { at, count });
+    }
+
+    fn edit(&mut self, sheet: usize, axis: Axis, edit: Edit) {
+        let Some(target) = self.sheet_name(sheet).map(str::to_lowercase) else {
+            return;
+        };
+        for (idx, (_, s)) in self.sheets.iter_mut().enumerate() {
+            if idx == sheet {
+                s.map = s
+                    .map
+                    .drain()
+                    .filter_map(|((x, y), cell)| match axis {
+                        Axis::Column => Some(((edit.id(x)?, y), cell)),
+                        Axis::Row => Some(((x, edit.id(y)?), cell)),
+                    })
+                    .collect();
+            }
+            for cell in s.map.values_mut() {
+                if let Some(expr) = cell.expr.take() {
+                    cell.expr = Some(edit.adjust_expr(expr, idx == sheet, &target, axis));
+                }
+            }
+        }
+    }
+}
+
+/// The direction of rows or columns that are inserted or deleted.
+#[derive(Debug, Clone, Copy)]
+enum Axis {
+    Column,
+    Row,
+}
+
+/// An insertion or deletion of rows or columns.
+#[derive(Debug, Clone, Copy)]
+enum Edit {
+    Insert { at: usize, count: usize },
+    Delete { at: usize, count: usize },
+}
+
+impl Edit {
+    /// Returns the new id of a row or column, `None` if it is deleted or
+    /// moved off the sheet.
+    fn id(self, id: usize) -> Option<usize> {
+        match self {
+            Self::Insert { at, count } if id >= at => {
+                id.checked_add(count).filter(|id| *id <= Coord::MAX_ID)
+            }
+            Self::Delete { at, count } if id >= at && id - at >= count => Some(id - count),
+            Self::Delete { at, .. } if id >= at => None,
+            _ => Some(id),
+        }
+    }
+
+    fn coord(self, c: Coord) -> Option<Coord> {
+        Some(Coord {
+            id: self.id(c.id)?,
+            ..c
+        })
+    }
+
+    /// Returns the new start and end of a range, `None` if all of it is deleted.
+    fn bounds(self, a: Coord, b: Coord) -> Option<(Coord, Coord)> {
+        match self {
+            Self::Insert { .. } => Some((self.coord(a)?, self.coord(b)?)),
+            Self::Delete { at, .. } => {
+                // partially deleted ranges keep their remaining rows or columns
+                let a = self.coord(a).unwrap_or(Coord { id: at, ..a });
+                let b = self.coord(b).or_else(|| {
+                    Some(Coord {
+                        id: at.checked_sub(1)?,
+                        ..b
+                    })
+                })?;
+                (a.id <= b.id).then_some((a, b))
+            }
+        }
+    }
+
+    /// Adjusts all references of a formula, references to deleted cells
+    /// become `#REF!`.
+    fn adjust_expr(self, expr: Expr, here: bool, target: &str, axis: Axis) -> Expr {
+        expr.map(&mut |e| match e {
+            Expr::Ref(r) => self
+                .adjust_ref(&r, here, target, axis)
+                .map_or(Expr::Error(Error::Ref), Expr::Ref),
+            e => e,
+        })
+    }
+
+    /// Adjusts a reference of a formula, `here` tells if the formula is in
+    /// the edited sheet.
+    fn adjust_ref(self, r: &Ref, here: bool, target: &str, axis: Axis) -> Option<Ref> {
+        let is_target = |sheet: &SheetName| sheet.name.to_lowercase() == target;
+        Some(match (r, axis) {
+            (r, _) if !here && !matches!(r, Ref::SheetRef(..) | Ref::SheetRange(..)) => r.clone(),
+            (Ref::CellRef(col, row), Axis::Column) => Ref::CellRef(self.coord(*col)?, *row),
+            (Ref::CellRef(col, row), Axis::Row) => Ref::CellRef(*col, self.coord(*row)?),
+            (Ref::ColumnRange(a, b), Axis::Column) => {
+                let (a, b) = self.bounds(*a, *b)?;
+                Ref::ColumnRange(a, b)
+            }
+            (Ref::RowRange(a, b), Axis::Row) => {
+                let (a, b) = self.bounds(*a, *b)?;
+                Ref::RowRange(a, b)
+            }
+            (Ref::CellRange((c1, r1), (c2, r2)), Axis::Column) => {
+                let (c1, c2) = self.bounds(*c1, *c2)?;
+                Ref::CellRange((c1, *r1), (c2, *r2))
+            }
+            (Ref::CellRange((c1, r1), (c2, r2)), Axis::Row) => {
+                let (r1, r2) = self.bounds(*r1, *r2)?;
+                Ref::CellRange((*c1, r1), (*c2, r2))
+            }
+            (Ref::SheetRef(sheet, r), _) => Ref::SheetRef(
+                sheet.clone(),
+                Box::new(self.adjust_ref(r, is_target(sheet), target, axis)?),
+            ),
+            // references spanning several sheets are only adjusted if they
+            // only span the edited sheet
+            (Ref::SheetRange(a, b, r), _) => Ref::SheetRange(
+                a.clone(),
+                b.clone(),
+                Box::new(self.adjust_ref(r, is_target(a) && is_target(b), target, axis)?),
+            ),
+            (r, _) => r.clone(),
+        })
+    }
 }
 
 /// An OpenFormula sheet.
@@ -113,12 +609,19 @@ where
 ///
 /// Apply implied intersection if multiple cells are referenced.
 pub fn eval_ref(ctx: &Context, r: &Ref) -> Result<Value> {
+    eval_sheet_ref(ctx, &ctx.workbook, ctx.current_sheet, r)
+}
+
+/// Evaluate a reference into the sheet with the given index of a workbook.
+fn eval_sheet_ref(ctx: &Context, workbook: &Workbook, sheet: usize, r: &Ref) -> Result<Value> {
     match r {
         // evaluate single cell reference
         Ref::CellRef(x, y) => {
-            let cell = ctx.sheet.get(*x, *y);
+            let cell = workbook.sheet(sheet).ok_or(Error::Ref)?.get(x.id, y.id);
             if let Some(cell) = cell {
-                if let Some(val) = cell.value.clone() {
+                if let Some(Value::Error(e)) = cell.value {
+                    Err(e)
+                } else if let Some(val) = cell.value.clone() {
                     Ok(val)
                 } else {
                     Err(Error::Ref)
@@ -130,11 +633,11 @@ pub fn eval_ref(ctx: &Context, r: &Ref) -> Result<Value> {
         // implied intersection
         Ref::ColumnRange(x1, x2) => {
             if let Some((x, y)) = ctx.current_loc {
-                if *x1 != *x2 || x == *x1 {
+                if x1.id != x2.id || x == x1.id {
                     Err(Error::Value)
                 } else {
-                    let r = Ref::CellRef(*x1, y);
-                    eval_ref(ctx, &r)
+                    let r = Ref::CellRef(*x1, y.into());
+                    eval_sheet_ref(ctx, workbook, sheet, &r)
                 }
             } else {
                 Err(Error::Ref)
@@ -142,35 +645,36 @@ pub fn eval_ref(ctx: &Context, r: &Ref) -> Result<Value> {
         }
         Ref::RowRange(y1, y2) => {
             if let Some((x, y)) = ctx.current_loc {
-                if *y1 != *y2 || y == *y1 {
+                if y1.id != y2.id || y == y1.id {
                     Err(Error::Value)
                 } else {
-                    let r = Ref::CellRef(x, *y1);
-                    eval_ref(ctx, &r)
+                    let r = Ref::CellRef(x.into(), *y1);
+                    eval_sheet_ref(ctx, workbook, sheet, &r)
                 }
             } else {
                 Err(Error::Ref)
             }
         }
-        Ref::CellRange((x1, y1), (x2, y2)) => {
-            assert!(*x1 <= *x2);
-            assert!(*y1 <= *y2);
+        Ref::CellRange((xa, ya), (xb, yb)) => {
+            // ranges that are built by hand may have their corners in any order
+            let (x1, x2) = if xa.id <= xb.id { (xa, xb) } else { (xb, xa) };
+            let (y1, y2) = if ya.id <= yb.id { (ya, yb) } else { (yb, ya) };
             if let Some((x, y)) = ctx.current_loc {
-                if x >= *x1 && x <= *x2 {
+                if x >= x1.id && x <= x2.id {
                     // columns overlap
-                    if *y1 != *y2 || y == *y1 {
+                    if y1.id != y2.id || y == y1.id {
                         Err(Error::Value)
                     } else {
-                        let r = Ref::CellRef(x, *y1);
-                        eval_ref(ctx, &r)
+                        let r = Ref::CellRef(x.into(), *y1);
+                        eval_sheet_ref(ctx, workbook, sheet, &r)
                     }
-                } else if y >= *y1 && y <= *y2 {
+                } else if y >= y1.id && y <= y2.id {
                     // rows overlap
-                    if *x1 != *x2 || x == *x1 {
+                    if x1.id != x2.id || x == x1.id {
                         Err(Error::Value)
                     } else {
-                        let r = Ref::CellRef(*x1, y);
-                        eval_ref(ctx, &r)
+                        let r = Ref::CellRef(*x1, y.into());
+                        eval_sheet_ref(ctx, workbook, sheet, &r)
                     }
                 } else {
                     // no overlap, intersection is empty
@@ -180,16 +684,40 @@ pub fn eval_ref(ctx: &Context, r: &Ref) -> Result<Value> {
                 Err(Error::Ref)
             }
         }
+        Ref::SheetRef(name, r) => {
+            let sheet = workbook.sheet_index(&name.name).ok_or(Error::Ref)?;
+            eval_sheet_ref(ctx, workbook, sheet, r)
+        }
+        Ref::SheetRange(a, b, r) => {
+            let a = workbook.sheet_index(&a.name).ok_or(Error::Ref)?;
+            let b = workbook.sheet_index(&b.name).ok_or(Error::Ref)?;
+            if a == b {
+                eval_sheet_ref(ctx, workbook, a, r)
+            } else {
+                // implied intersection never spans multiple sheets
+                Err(Error::Value)
+            }
+        }
+        // references without a sheet name point into the first sheet of the document
+        Ref::External(iri, r) => {
+            let workbook = ctx
+                .external
+                .as_ref()
+                .and_then(|external| external.workbook(iri))
+                .ok_or(Error::Ref)?;
+            eval_sheet_ref(ctx, workbook, 0, r)
+        }
     }
 }
 
 /// Evaluates an OpenFormula function with the given name and args.
 ///
 /// This will return the evaluated value, or an error if the function does
-/// not exist.
+/// not exist. Function names are resolved by [`Functions::resolve`], so e.g.
+/// `abs(-1)` and `ORG.OPENOFFICE.ABS(-1)` call the predefined `ABS` function.
 pub fn eval_fn(ctx: &Context, fname: &str, args: &[Expr]) -> Result<Value> {
     use crate::functions::*;
-    match fname {
+    match ctx.functions.resolve(fname).as_str() {
         // first check for functions defined at compile time
         "ABS" => abs(args, ctx),
         "ACOS" => acos(args, ctx),
@@ -213,6 +741,21 @@ pub fn eval_fn(ctx: &Context, fname: &str, args: &[Expr]) -> Result<Value> {
     }
 }
 
+/// Evaluates the definition of a named expression.
+///
+/// Unknown names evaluate to `#NAME?`, circular definitions to `#REF!`.
+fn eval_name(ctx: &Context, name: &str) -> Result<Value> {
+    let expr = ctx.names.get(ctx.current_sheet, name).ok_or(Error::Name)?;
+    let key = (ctx.current_sheet, name.to_lowercase());
+    if ctx.names.resolving.borrow().contains(&key) {
+        return Err(Error::Ref);
+    }
+    ctx.names.resolving.borrow_mut().push(key);
+    let v = eval(ctx, expr);
+    ctx.names.resolving.borrow_mut().pop();
+    v
+}
+
 /// Evaluates an OpenFormula expression.
 pub fn eval(ctx: &Context, expr: &Expr) -> Result<Value> {
     trace!("{:?}", expr);
@@ -235,6 +778,21 @@ pub fn eval(ctx: &Context, expr: &Expr) -> Result<Value> {
         Expr::Pow(l, r) => eval_to_num_2(ctx, l, r, |l, r| Ok(l.powf(r))),
         Expr::Ref(r) => Ok(Value::Ref(r.clone())),
         Expr::Func(fname, args) => eval_fn(ctx, fname, args),
+        Expr::Name(name) => eval_name(ctx, name),
+        Expr::Error(e) => Err(*e),
+        // errors are stored as array elements and do not fail the whole array
+        Expr::Array(rows) => Ok(Value::Array(
+            rows.iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|e| eval(ctx, e).unwrap_or_else(Value::Error))
+                        .collect()
+                })
+                .collect(),
+        )),
+        // incomplete formulas can't be evaluated
+        Expr::Missing => Err(Error::Value),
+        Expr::Empty => Ok(Value::EmptyCell),
         _ => Err(Error::Unimplemented),
     };
     trace!("{:?} → {:?}", expr, v);
@@ -338,32 +896,27 @@ mod tests {
 
     fn ods_to_expr(formula: &str) -> Expr {
         // FIXME: proper checking/parsing (remove unwraps)
-        let formula = formula.strip_prefix("of:=").unwrap();
-        // this regex replaces Open Document Format references with
-        // proper spreadsheet references, e.g. [.A1] →  A1, or [.A1:B2] →  A1:B2
-        // FIXME: is this the best way to handle this? Maybe add an open document option to the parser?
-        let re = regex::Regex::new(r"\[.([A-Z0-9]+)((:)\.([A-Z0-9]+))?\]").unwrap();
-        let formula = re.replace_all(formula, "$1$3$4").into_owned();
-
-        // FIXME: proper checking/parsing (remove unwraps)
-        let res = parser().parse(&formula);
+        let res = parser().parse(formula);
         trace!("{:?}", res);
         res.unwrap()
     }
 
-    fn load_ods(path: &'static str) -> Sheet {
-        let mut sheet = Sheet::default();
+    fn load_ods(path: &'static str) -> Workbook {
+        let mut workbook = Workbook::empty();
         let wb = spreadsheet_ods::read_ods(path).unwrap();
-        let ods_sheet = wb.sheet(0);
-        ods_sheet.iter().fold((), |_, ((y, x), cell)| {
-            let cell = Cell {
-                value: ods_to_value(cell.value()),
-                expr: cell.formula().map(|f| ods_to_expr(f)),
-            };
-            trace!("{}, {}: {:?}", x, y, cell);
-            sheet.set(x as usize, y as usize, cell);
-        });
-        sheet
+        for ods_sheet in wb.iter_sheets() {
+            let mut sheet = Sheet::default();
+            ods_sheet.iter().fold((), |_, ((y, x), cell)| {
+                let cell = Cell {
+                    value: ods_to_value(cell.value()),
+                    expr: cell.formula().map(|f| ods_to_expr(f)),
+                };
+                trace!("{}, {}: {:?}", x, y, cell);
+                sheet.set(x as usize, y as usize, cell);
+            });
+            workbook.add_sheet(ods_sheet.name().as_str(), sheet);
+        }
+        workbook
     }
 
     #[dir_test(
@@ -371,23 +924,366 @@ mod tests {
         glob: "**/*.ods",
         loader: load_ods,
     )]
-    fn test_ods(fixture: Fixture<Sheet>) {
+    fn test_ods(fixture: Fixture<Workbook>) {
         // FIXME: clone may not be necessary here, maybe construct context later
         let mut ctx = Context {
-            sheet: fixture.content().clone(),
+            workbook: fixture.content().clone(),
             ..Default::default()
         };
-        for ((x, y), cell) in ctx.sheet.iter() {
-            ctx.current_loc = Some((x, y));
-            trace!("{},{}: {:?}", x, y, cell);
-            if cell.value.is_some() && cell.expr.is_some() {
-                let val = cell.value.clone().unwrap();
-                let expr = cell.expr.clone().unwrap();
-                // FIXME: is Scalar the right type for single cell evaluation? may depend on cell format
-                let eval_val = eval(&ctx, &expr).convert_to_scalar(&ctx);
-                trace!("{:?}", eval_val);
-                assert_eq!(Ok(val), eval_val);
+        for idx in 0..ctx.workbook.len() {
+            ctx.current_sheet = idx;
+            for ((x, y), cell) in ctx.workbook.sheet(idx).unwrap().iter() {
+                ctx.current_loc = Some((x, y));
+                trace!("{},{}: {:?}", x, y, cell);
+                if cell.value.is_some() && cell.expr.is_some() {
+                    let val = cell.value.clone().unwrap();
+                    let expr = cell.expr.clone().unwrap();
+                    // FIXME: is Scalar the right type for single cell evaluation? may depend on cell format
+                    let eval_val = eval(&ctx, &expr).convert_to_scalar(&ctx);
+                    trace!("{:?}", eval_val);
+                    assert_eq!(Ok(val), eval_val);
+                    // formulas survive a round trip through the printer
+                    let formula = expr.to_formula_string();
+                    assert_eq!(parser().parse(&formula).unwrap(), expr);
+                }
             }
         }
     }
+
+    fn num_cell(n: f64) -> Cell {
+        Cell {
+            value: Some(Value::Num(n)),
+            expr: None,
+        }
+    }
+
+    fn check(ctx: &Context, formula: &str, val: Result<Value>) {
+        let expr = parser().parse(formula).unwrap();
+        assert_eq!(eval(ctx, &expr).convert_to_scalar(ctx), val, "{}", formula);
+    }
+
+    #[test]
+    fn sheet_refs() {
+        let mut workbook = Workbook::default();
+        let mut sheet = Sheet::default();
+        sheet.set(0, 0, num_cell(2.0));
+        workbook.add_sheet("Sheet2", sheet);
+        let mut sheet = Sheet::default();
+        sheet.set(0, 0, num_cell(3.0));
+        workbook.add_sheet("My Sheet", sheet);
+        workbook.sheet_mut(0).unwrap().set(0, 0, num_cell(1.0));
+        let ctx = Context {
+            workbook,
+            current_loc: Some((1, 0)),
+            ..Default::default()
+        };
+
+        check(&ctx, "A1", Ok(Value::Num(1.0)));
+        check(&ctx, "Sheet2.A1", Ok(Value::Num(2.0)));
+        check(&ctx, "[$Sheet2.A1]", Ok(Value::Num(2.0)));
+        check(&ctx, "'My Sheet'.A1", Ok(Value::Num(3.0)));
+        check(&ctx, "sheet1.A1", Ok(Value::Num(1.0)));
+        check(&ctx, "Sheet2.A1+'My Sheet'.A1", Ok(Value::Num(5.0)));
+        check(&ctx, "Missing.A1", Err(Error::Ref));
+        check(&ctx, "Sheet2.A1:Sheet2.A1", Ok(Value::Num(2.0)));
+        check(&ctx, "Sheet1.A1:Sheet2.A1", Err(Error::Value));
+    }
+
+    #[test]
+    fn inverted_ranges() {
+        let mut ctx = Context {
+            current_loc: Some((1, 2)),
+            ..Default::default()
+        };
+        ctx.sheet_mut().unwrap().set(0, 2, num_cell(1.0));
+        ctx.sheet_mut().unwrap().set(1, 0, num_cell(2.0));
+
+        check(&ctx, "A5:A1", Ok(Value::Num(1.0)));
+        check(&ctx, "B1:D1", Ok(Value::Num(2.0)));
+        check(&ctx, "D1:B1", Ok(Value::Num(2.0)));
+        check(&ctx, "B5:A1", Err(Error::Value));
+        check(&ctx, "21:9", Err(Error::Value));
+
+        // references that bypass the normalizing constructors
+        let r = Ref::CellRange(
+            (Coord::relative(0), Coord::relative(4)),
+            (Coord::relative(0), Coord::relative(0)),
+        );
+        assert_eq!(eval_ref(&ctx, &r), Ok(Value::Num(1.0)));
+        let r = Ref::CellRange(
+            (Coord::relative(3), Coord::relative(0)),
+            (Coord::relative(1), Coord::relative(0)),
+        );
+        assert_eq!(eval_ref(&ctx, &r), Ok(Value::Num(2.0)));
+    }
+
+    #[test]
+    fn external_refs() {
+        let mut rates = Workbook::default();
+        rates.sheet_mut(0).unwrap().set(0, 0, num_cell(2.0));
+        let mut sheet = Sheet::default();
+        sheet.set(1, 1, num_cell(3.0));
+        rates.add_sheet("Other", sheet);
+        let mut resolver = MemoryResolver::default();
+        resolver.insert("file:///data/rates.ods", rates);
+        let mut workbook = Workbook::default();
+        workbook.sheet_mut(0).unwrap().set(0, 0, num_cell(1.0));
+        let ctx = Context {
+            workbook,
+            external: Some(Box::new(resolver)),
+            ..Default::default()
+        };
+
+        check(&ctx, "'file:///data/rates.ods'#A1", Ok(Value::Num(2.0)));
+        check(
+            &ctx,
+            "['file:///data/rates.ods'#$Sheet1.A1]+A1",
+            Ok(Value::Num(3.0)),
+        );
+        check(
+            &ctx,
+            "'file:///data/rates.ods'#Other.B2",
+            Ok(Value::Num(3.0)),
+        );
+        check(&ctx, "'file:///data/rates.ods'#Missing.B2", Err(Error::Ref));
+        check(&ctx, "'file:///data/other.ods'#A1", Err(Error::Ref));
+        let ctx = Context::default();
+        let expr = parser().parse("'file:///data/rates.ods'#A1").unwrap();
+        assert_eq!(eval(&ctx, &expr).convert_to_scalar(&ctx), Err(Error::Ref));
+    }
+
+    #[test]
+    fn insert_delete() {
+        let formula_cell = |formula: &str| Cell {
+            value: None,
+            expr: Some(parser().parse(formula).unwrap()),
+        };
+        let mut workbook = Workbook::default();
+        workbook.add_sheet("Sheet2", Sheet::default());
+        let sheet = workbook.sheet_mut(0).unwrap();
+        sheet.set(0, 0, num_cell(1.0));
+        sheet.set(0, 4, num_cell(5.0));
+        sheet.set(
+            2,
+            9,
+            formula_cell("A5+SUM(A1:A6)+$A$5*B4+SUM(3:7)+SUM(A:A)"),
+        );
+        workbook.sheet_mut(1).unwrap().set(
+            0,
+            0,
+            formula_cell("Sheet1.A5+A5+Sheet1.A1:B9+Sheet1.A1:Sheet2.B9"),
+        );
+        let formula = |workbook: &Workbook, sheet, x, y| {
+            let cell = workbook.sheet(sheet).unwrap().get(x, y).unwrap();
+            cell.expr.as_ref().unwrap().to_string()
+        };
+
+        // rows inserted above row 5
+        let mut w = workbook.clone();
+        w.insert_rows(0, 4, 2);
+        assert!(w.sheet(0).unwrap().has_cell(0, 6));
+        assert!(!w.sheet(0).unwrap().has_cell(0, 4));
+        assert_eq!(
+            formula(&w, 0, 2, 11),
+            "A7+SUM(A1:A8)+$A$7*B4+SUM(3:9)+SUM(A:A)"
+        );
+        assert_eq!(
+            formula(&w, 1, 0, 0),
+            "Sheet1.A7+A5+Sheet1.A1:B11+Sheet1.A1:Sheet2.B9"
+        );
+
+        // rows 4 and 5 deleted
+        let mut w = workbook.clone();
+        w.delete_rows(0, 3, 2);
+        assert!(!w.sheet(0).unwrap().has_cell(0, 4));
+        assert!(w.sheet(0).unwrap().has_cell(0, 0));
+        assert_eq!(
+            formula(&w, 0, 2, 7),
+            "#REF!+SUM(A1:A4)+#REF!*#REF!+SUM(3:5)+SUM(A:A)"
+        );
+        assert_eq!(
+            formula(&w, 1, 0, 0),
+            "#REF!+A5+Sheet1.A1:B7+Sheet1.A1:Sheet2.B9"
+        );
+
+        // ranges that are deleted entirely or lose their start
+        let mut w = workbook.clone();
+        w.delete_rows(0, 0, 3);
+        assert_eq!(
+            formula(&w, 0, 2, 6),
+            "A2+SUM(A1:A3)+$A$2*B1+SUM(1:4)+SUM(A:A)"
+        );
+        w.delete_rows(0, 0, 4);
+        assert_eq!(w.sheet(0).unwrap().iter().count(), 1);
+        assert_eq!(
+            formula(&w, 1, 0, 0),
+            "#REF!+A5+Sheet1.A1:B2+Sheet1.A1:Sheet2.B9"
+        );
+
+        // columns
+        let mut w = workbook.clone();
+        w.insert_columns(0, 1, 1);
+        assert_eq!(
+            formula(&w, 0, 3, 9),
+            "A5+SUM(A1:A6)+$A$5*C4+SUM(3:7)+SUM(A:A)"
+        );
+        w.delete_columns(0, 0, 1);
+        assert_eq!(
+            formula(&w, 0, 2, 9),
+            "#REF!+SUM(#REF!)+#REF!*B4+SUM(3:7)+SUM(#REF!)"
+        );
+
+        // sheets that don't exist are ignored
+        let mut w = workbook.clone();
+        w.delete_rows(5, 0, 1);
+        assert_eq!(formula(&w, 1, 0, 0), formula(&workbook, 1, 0, 0));
+
+        // huge counts delete everything below or push it off the sheet
+        let mut w = workbook.clone();
+        w.delete_rows(0, 5, usize::MAX);
+        assert_eq!(
+            formula(&w, 1, 0, 0),
+            "Sheet1.A5+A5+Sheet1.A1:B5+Sheet1.A1:Sheet2.B9"
+        );
+        assert_eq!(w.sheet(0).unwrap().iter().count(), 2);
+        let mut w = workbook.clone();
+        w.sheet_mut(1)
+            .unwrap()
+            .set(1, 0, formula_cell("Sheet1.A1+Sheet1.B$1"));
+        w.insert_rows(0, 0, usize::MAX);
+        assert_eq!(w.sheet(0).unwrap().iter().count(), 0);
+        assert_eq!(formula(&w, 1, 1, 0), "#REF!+#REF!");
+        let mut w = workbook.clone();
+        w.insert_columns(0, 1, usize::MAX);
+        assert_eq!(w.sheet(0).unwrap().iter().count(), 2);
+        assert_eq!(
+            formula(&w, 1, 0, 0),
+            "Sheet1.A5+A5+#REF!+Sheet1.A1:Sheet2.B9"
+        );
+    }
+
+    #[test]
+    fn error_values() {
+        let mut ctx = Context::default();
+        ctx.sheet_mut().unwrap().set(
+            0,
+            0,
+            Cell {
+                value: Some(Value::Error(Error::NotAvailable)),
+                expr: None,
+            },
+        );
+
+        check(&ctx, "#DIV/0!", Err(Error::Div0));
+        check(&ctx, "1+#VALUE!", Err(Error::Value));
+        check(&ctx, "-#NUM!", Err(Error::Num));
+        check(&ctx, "A1", Err(Error::NotAvailable));
+        check(&ctx, "A1*2", Err(Error::NotAvailable));
+    }
+
+    #[test]
+    fn inline_arrays() {
+        let ctx = Context::default();
+        let expr = parser().parse("{1;\"A\"|#N/A;TRUE}").unwrap();
+        assert_eq!(
+            eval(&ctx, &expr),
+            Ok(Value::Array(vec![
+                vec![Value::Num(1.0), Value::String("A".into())],
+                vec![Value::Error(Error::NotAvailable), Value::Bool(true)],
+            ]))
+        );
+        assert_eq!(
+            eval(&ctx, &expr).convert_to_scalar(&ctx),
+            Ok(Value::Num(1.0))
+        );
+        let expr = parser().parse("{2;3}*2").unwrap();
+        assert_eq!(eval(&ctx, &expr), Ok(Value::Num(4.0)));
+    }
+
+    #[test]
+    fn empty_params() {
+        let mut ctx = Context::default();
+        // returns the second parameter or a default of 10 if it is omitted
+        ctx.functions.insert(
+            "DEFAULT".into(),
+            Box::new(|args, ctx| match args {
+                [_, arg] if arg.is_empty() => Ok(Value::Num(10.0)),
+                [_, arg] => eval(ctx, arg),
+                _ => Err(Error::Args),
+            }),
+        );
+        check(&ctx, "DEFAULT(1;)", Ok(Value::Num(10.0)));
+        check(&ctx, "DEFAULT(1;0)", Ok(Value::Num(0.0)));
+        check(&ctx, "DEFAULT(1;\"\")", Ok(Value::String("".into())));
+        check(&ctx, "DEFAULT(1)", Err(Error::Args));
+    }
+
+    #[test]
+    fn function_names() {
+        let mut ctx = Context::default();
+        ctx.functions
+            .insert("IfError".into(), Box::new(|_, _| Ok(Value::Num(1.0))));
+        ctx.functions.insert(
+            "COM.MICROSOFT.CEILING".into(),
+            Box::new(|_, _| Ok(Value::Num(2.0))),
+        );
+        ctx.functions.alias("BETRAG", "ABS");
+        ctx.functions
+            .insert("FLOOR".into(), Box::new(|_, _| Ok(Value::Num(3.0))));
+        check(&ctx, "abs(-1)", Ok(Value::Num(1.0)));
+        check(&ctx, "ORG.OPENOFFICE.ABS(-2)", Ok(Value::Num(2.0)));
+        check(&ctx, "Betrag(-3)", Ok(Value::Num(3.0)));
+        check(&ctx, "IFERROR()", Ok(Value::Num(1.0)));
+        check(&ctx, "COM.MICROSOFT.IFERROR()", Ok(Value::Num(1.0)));
+        check(&ctx, "com.microsoft.ceiling()", Ok(Value::Num(2.0)));
+        check(&ctx, "CEILING()", Err(Error::Name));
+        // functions with Excel semantics don't resolve to OpenFormula functions
+        check(&ctx, "COM.MICROSOFT.FLOOR()", Err(Error::Name));
+        check(&ctx, "ORG.OPENOFFICE.ERRORTYPE()", Err(Error::Name));
+    }
+
+    #[test]
+    fn named_expressions() {
+        let mut workbook = Workbook::default();
+        workbook.add_sheet("Sheet2", Sheet::default());
+        workbook.sheet_mut(0).unwrap().set(0, 0, num_cell(100.0));
+        workbook.sheet_mut(0).unwrap().set(1, 0, num_cell(200.0));
+        let mut ctx = Context {
+            workbook,
+            ..Default::default()
+        };
+        ctx.names.define("TaxRate", Expr::Num(0.5));
+        ctx.names
+            .define("Net", parser().parse("$Sheet1.$A$1").unwrap());
+        ctx.names.define_local(1, "TaxRate", Expr::Num(0.25));
+        ctx.names
+            .define("Gross", parser().parse("Net*(1+TaxRate)").unwrap());
+        ctx.names.define("Loop", parser().parse("Again+1").unwrap());
+        ctx.names.define("Again", parser().parse("Loop").unwrap());
+
+        check(&ctx, "TaxRate*Net", Ok(Value::Num(50.0)));
+        check(&ctx, "taxrate*B1", Ok(Value::Num(100.0)));
+        check(&ctx, "Gross", Ok(Value::Num(150.0)));
+        check(&ctx, "Unknown+1", Err(Error::Name));
+        check(&ctx, "Loop", Err(Error::Ref));
+        ctx.current_sheet = 1;
+        check(&ctx, "TaxRate*Net", Ok(Value::Num(25.0)));
+        check(&ctx, "Gross", Ok(Value::Num(125.0)));
+        ctx.names.undefine_local(1, "TAXRATE");
+        check(&ctx, "TaxRate", Ok(Value::Num(0.5)));
+
+        // names follow the cells they refer to
+        ctx.current_sheet = 0;
+        ctx.names
+            .define_local(0, "Price", parser().parse("B1").unwrap());
+        ctx.insert_rows(0, 0, 1);
+        ctx.insert_columns(0, 0, 2);
+        check(&ctx, "Net", Ok(Value::Num(100.0)));
+        check(&ctx, "Price", Ok(Value::Num(200.0)));
+        check(&ctx, "Gross", Ok(Value::Num(150.0)));
+        assert_eq!(ctx.names.get(0, "Net").unwrap().to_string(), "$Sheet1.$C$2");
+        ctx.delete_rows(0, 1, 1);
+        check(&ctx, "Net", Err(Error::Ref));
+        check(&ctx, "Price", Err(Error::Ref));
+    }
 }
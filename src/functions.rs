@@ -4,6 +4,72 @@ use crate::conversion::*;
 use crate::eval::{eval, Context};
 use crate::types::{Error, Expr, Result, Value};
 
+/// Namespace prefixes of application specific function names, e.g.
+/// `ORG.OPENOFFICE.` in `ORG.OPENOFFICE.ERRORTYPE`.
+pub const NAMESPACES: &[&str] = &["ORG.OPENOFFICE.", "ORG.LIBREOFFICE.", "ORG.GNUMERIC."];
+
+/// Functions of the `COM.MICROSOFT.` namespace that behave like the
+/// OpenFormula function of the same name or of their [alias](ALIASES).
+///
+/// Other functions keep the namespace, e.g. `COM.MICROSOFT.CEILING` rounds
+/// negative numbers differently than `CEILING`.
+pub const MICROSOFT_FUNCTIONS: &[&str] = &[
+    "COVARIANCE.P",
+    "IFERROR",
+    "IFNA",
+    "MODE.SNGL",
+    "PERCENTILE.INC",
+    "QUARTILE.INC",
+    "RANK.EQ",
+    "STDEV.P",
+    "STDEV.S",
+    "VAR.P",
+    "VAR.S",
+];
+
+/// Function names used by other producers that are mapped to the
+/// OpenFormula function with the same behavior.
+pub const ALIASES: &[(&str, &str)] = &[
+    ("COVARIANCE.P", "COVAR"),
+    ("MODE.SNGL", "MODE"),
+    ("PERCENTILE.INC", "PERCENTILE"),
+    ("QUARTILE.INC", "QUARTILE"),
+    ("RANK.EQ", "RANK"),
+    ("STDEV.P", "STDEVP"),
+    ("STDEV.S", "STDEV"),
+    ("VAR.P", "VARP"),
+    ("VAR.S", "VAR"),
+];
+
+/// Returns the OpenFormula name of a function as written by any producer.
+///
+/// Names are converted to uppercase, namespace prefixes are stripped and
+/// aliases are replaced. The `COM.MICROSOFT.` prefix is only stripped from
+/// the [`MICROSOFT_FUNCTIONS`].
+///
+/// Example
+/// ```rust
+/// use open_formula::functions::canonical_name;
+/// assert_eq!(canonical_name("sum"), "SUM");
+/// assert_eq!(canonical_name("COM.MICROSOFT.IFERROR"), "IFERROR");
+/// assert_eq!(canonical_name("com.microsoft.stdev.s"), "STDEV");
+/// assert_eq!(canonical_name("COM.MICROSOFT.CEILING"), "COM.MICROSOFT.CEILING");
+/// ```
+pub fn canonical_name(name: &str) -> String {
+    let name = name.to_uppercase();
+    let microsoft = name
+        .strip_prefix("COM.MICROSOFT.")
+        .filter(|f| MICROSOFT_FUNCTIONS.contains(f));
+    let name = microsoft
+        .or_else(|| NAMESPACES.iter().find_map(|ns| name.strip_prefix(ns)))
+        .unwrap_or(&name);
+    ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, f)| f)
+        .to_string()
+}
+
 fn single_num_fn<F>(args: &[Expr], ctx: &Context, f: F) -> Result<Value>
 where
     F: Fn(f64) -> Result<f64>,
@@ -95,3 +161,26 @@ pub fn sqrt(args: &[Expr], ctx: &Context) -> Result<Value> {
 pub fn tan(args: &[Expr], ctx: &Context) -> Result<Value> {
     single_num_fn(args, ctx, |x| Ok(x.tan()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_names() {
+        assert_eq!(canonical_name("ABS"), "ABS");
+        assert_eq!(canonical_name("Log10"), "LOG10");
+        assert_eq!(canonical_name("ORG.OPENOFFICE.ERRORTYPE"), "ERRORTYPE");
+        assert_eq!(canonical_name("org.libreoffice.rawsubtract"), "RAWSUBTRACT");
+        assert_eq!(canonical_name("VAR.S"), "VAR");
+        assert_eq!(canonical_name("ERROR.TYPE"), "ERROR.TYPE");
+        assert_eq!(canonical_name("COM.MICROSOFT"), "COM.MICROSOFT");
+        assert_eq!(canonical_name("com.microsoft.ifna"), "IFNA");
+        assert_eq!(canonical_name("COM.MICROSOFT.VAR.P"), "VARP");
+        assert_eq!(canonical_name("COM.MICROSOFT.FLOOR"), "COM.MICROSOFT.FLOOR");
+        assert_eq!(
+            canonical_name("com.microsoft.concat"),
+            "COM.MICROSOFT.CONCAT"
+        );
+    }
+}
@@ -33,17 +33,43 @@ pub fn column_to_id<S: AsRef<str>>(col: S) -> Result<usize, RefError> {
     if len == 0 {
         return Err(RefError::EmptyReference);
     }
-    let mut sum = 0;
+    let mut sum: usize = 0;
     for c in col.chars() {
         if !c.is_ascii_uppercase() {
             return Err(RefError::MalformedReference);
         }
-        sum *= 26;
-        sum += (c as u8) as usize - 65 + 1;
+        // columns that don't fit a usize are malformed rather than wrapped
+        sum = sum
+            .checked_mul(26)
+            .and_then(|sum| sum.checked_add((c as u8 - b'A') as usize + 1))
+            .ok_or(RefError::MalformedReference)?;
     }
     Ok(sum - 1)
 }
 
+/// Convert a numeric column id to the corresponding column letter reference.
+///
+/// This is the inverse of [column_to_id].
+///
+/// Example
+/// ```rust
+/// use open_formula::helpers::id_to_column;
+/// assert_eq!(id_to_column(0), "A");
+/// assert_eq!(id_to_column(51), "AZ");
+/// assert_eq!(id_to_column(53), "BB");
+/// ```
+pub fn id_to_column(id: usize) -> String {
+    let mut chars = vec![];
+    // widened, so the id `usize::MAX` has a column as well
+    let mut n = id as u128 + 1;
+    while n > 0 {
+        n -= 1;
+        chars.push((b'A' + (n % 26) as u8) as char);
+        n /= 26;
+    }
+    chars.iter().rev().collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -63,5 +89,24 @@ mod tests {
         assert_eq!(column_to_id("Aa"), Err(RefError::MalformedReference));
         assert_eq!(column_to_id("aA"), Err(RefError::MalformedReference));
         assert_eq!(column_to_id("ab"), Err(RefError::MalformedReference));
+        assert_eq!(column_to_id("GKGWBYLWRXTLPO"), Ok(usize::MAX - 1));
+        assert_eq!(
+            column_to_id("ZZZZZZZZZZZZZZZ"),
+            Err(RefError::MalformedReference)
+        );
+    }
+
+    #[test]
+    fn column_letters() {
+        assert_eq!(id_to_column(0), "A");
+        assert_eq!(id_to_column(1), "B");
+        assert_eq!(id_to_column(25), "Z");
+        assert_eq!(id_to_column(26), "AA");
+        assert_eq!(id_to_column(27), "AB");
+        assert_eq!(id_to_column(16383), "XFD");
+        assert_eq!(id_to_column(usize::MAX), "GKGWBYLWRXTLPP");
+        for id in [0, 1, 25, 26, 700, 701, 702, 18277, 18278] {
+            assert_eq!(column_to_id(id_to_column(id)), Ok(id));
+        }
     }
 }
@@ -2,18 +2,25 @@
 
 #[cfg(feature = "small")]
 pub mod conversion;
+pub mod diagnostics;
 #[cfg(feature = "small")]
 pub mod eval;
 pub mod functions;
 pub mod helpers;
+#[cfg(feature = "ooxml")]
+pub mod ooxml;
 pub mod parser;
+pub mod printer;
 #[cfg(feature = "sql")]
 pub mod sql;
+pub mod tokenizer;
 pub mod types;
+pub mod visit;
 pub mod xmlchar;
 
 pub mod prelude {
-    pub use super::eval::{Cell, Context, Sheet, eval};
-    pub use super::parser::{Parser, parser};
+    pub use super::eval::{Cell, Context, Sheet, Workbook, eval};
+    pub use super::parser::{Parser, ParserConfig, RefStyle, parser, parser_with_config};
+    pub use super::tokenizer::{Token, TokenKind, tokenize};
     pub use super::types::{Expr, Value};
 }
@@ -0,0 +1,381 @@
+//! Translation between Excel (OOXML) formulas and OpenFormula expressions.
+//!
+//! Excel formulas are parsed with [`ParserConfig::excel`], so arguments are separated
+//! by `,`, sheets by `!` and inline array rows by `;`. Function names are mapped to their
+//! OpenFormula equivalents and back. The whitespace intersection and comma union operators
+//! of Excel are not supported, expressions using the reference intersection or union
+//! operators, references into other documents or names that Excel can't read can't be
+//! converted to Excel formulas.
+
+use std::fmt::Display;
+
+use chumsky::error::Rich;
+
+use crate::{
+    parser::{parser_with_config, Parser, ParserConfig},
+    printer::print_with_config,
+    types::{Expr, Ref},
+};
+
+/// A part of an OpenFormula expression that has no Excel syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExcelError {
+    /// The reference intersection operator `!`.
+    Intersection,
+    /// The reference union operator `~`.
+    Union,
+    /// A reference into another document, e.g. `'file:///a.ods'#Sheet1.A1`.
+    ExternalRef(String),
+    /// A name that is not a valid Excel name, e.g. `$$'Tax Rate'`.
+    Name(String),
+    /// An operand that is missing in an incomplete formula.
+    Missing,
+}
+
+impl Display for ExcelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Intersection => write!(f, "reference intersection is not supported by Excel"),
+            Self::Union => write!(f, "reference union is not supported by Excel"),
+            Self::ExternalRef(iri) => write!(f, "external reference to {} is not supported", iri),
+            Self::Name(name) => write!(f, "'{}' is not a valid Excel name", name),
+            Self::Missing => write!(f, "formula is incomplete"),
+        }
+    }
+}
+
+/// Function names that differ between Excel and OpenFormula.
+///
+/// Functions introduced after the original OOXML release are stored with the `_xlfn.`
+/// prefix in files, while OpenFormula either has its own name for them or places them
+/// in the `COM.MICROSOFT.` namespace.
+const FUNCTIONS: &[(&str, &str)] = &[
+    ("_xlfn.AGGREGATE", "COM.MICROSOFT.AGGREGATE"),
+    ("_xlfn.BETA.DIST", "COM.MICROSOFT.BETA.DIST"),
+    ("_xlfn.BETA.INV", "COM.MICROSOFT.BETA.INV"),
+    ("_xlfn.BINOM.DIST", "COM.MICROSOFT.BINOM.DIST"),
+    ("_xlfn.BINOM.INV", "COM.MICROSOFT.BINOM.INV"),
+    ("_xlfn.CEILING.MATH", "COM.MICROSOFT.CEILING.MATH"),
+    ("_xlfn.CEILING.PRECISE", "COM.MICROSOFT.CEILING.PRECISE"),
+    ("_xlfn.CHISQ.DIST", "COM.MICROSOFT.CHISQ.DIST"),
+    ("_xlfn.CHISQ.INV", "COM.MICROSOFT.CHISQ.INV"),
+    ("_xlfn.CONCAT", "COM.MICROSOFT.CONCAT"),
+    ("_xlfn.CONFIDENCE.NORM", "COM.MICROSOFT.CONFIDENCE.NORM"),
+    ("_xlfn.CONFIDENCE.T", "COM.MICROSOFT.CONFIDENCE.T"),
+    ("_xlfn.COVARIANCE.P", "COM.MICROSOFT.COVARIANCE.P"),
+    ("_xlfn.COVARIANCE.S", "COM.MICROSOFT.COVARIANCE.S"),
+    ("_xlfn.ERF.PRECISE", "COM.MICROSOFT.ERF.PRECISE"),
+    ("_xlfn.ERFC.PRECISE", "COM.MICROSOFT.ERFC.PRECISE"),
+    ("_xlfn.EXPON.DIST", "COM.MICROSOFT.EXPON.DIST"),
+    ("_xlfn.F.DIST", "COM.MICROSOFT.F.DIST"),
+    ("_xlfn.F.INV", "COM.MICROSOFT.F.INV"),
+    ("_xlfn.FLOOR.MATH", "COM.MICROSOFT.FLOOR.MATH"),
+    ("_xlfn.FLOOR.PRECISE", "COM.MICROSOFT.FLOOR.PRECISE"),
+    ("_xlfn.FORMULATEXT", "FORMULA"),
+    ("_xlfn.GAMMA.DIST", "COM.MICROSOFT.GAMMA.DIST"),
+    ("_xlfn.GAMMA.INV", "COM.MICROSOFT.GAMMA.INV"),
+    ("_xlfn.GAMMALN.PRECISE", "COM.MICROSOFT.GAMMALN.PRECISE"),
+    ("_xlfn.IFS", "COM.MICROSOFT.IFS"),
+    ("_xlfn.LOGNORM.DIST", "COM.MICROSOFT.LOGNORM.DIST"),
+    ("_xlfn.LOGNORM.INV", "COM.MICROSOFT.LOGNORM.INV"),
+    ("_xlfn.MAXIFS", "COM.MICROSOFT.MAXIFS"),
+    ("_xlfn.MINIFS", "COM.MICROSOFT.MINIFS"),
+    ("_xlfn.MODE.MULT", "COM.MICROSOFT.MODE.MULT"),
+    ("_xlfn.MODE.SNGL", "COM.MICROSOFT.MODE.SNGL"),
+    ("_xlfn.NEGBINOM.DIST", "COM.MICROSOFT.NEGBINOM.DIST"),
+    ("_xlfn.NORM.DIST", "COM.MICROSOFT.NORM.DIST"),
+    ("_xlfn.NORM.INV", "COM.MICROSOFT.NORM.INV"),
+    ("_xlfn.NORM.S.DIST", "COM.MICROSOFT.NORM.S.DIST"),
+    ("_xlfn.NORM.S.INV", "COM.MICROSOFT.NORM.S.INV"),
+    ("_xlfn.PERCENTILE.EXC", "COM.MICROSOFT.PERCENTILE.EXC"),
+    ("_xlfn.PERCENTILE.INC", "COM.MICROSOFT.PERCENTILE.INC"),
+    ("_xlfn.PERCENTRANK.EXC", "COM.MICROSOFT.PERCENTRANK.EXC"),
+    ("_xlfn.PERCENTRANK.INC", "COM.MICROSOFT.PERCENTRANK.INC"),
+    ("_xlfn.POISSON.DIST", "COM.MICROSOFT.POISSON.DIST"),
+    ("_xlfn.QUARTILE.EXC", "COM.MICROSOFT.QUARTILE.EXC"),
+    ("_xlfn.QUARTILE.INC", "COM.MICROSOFT.QUARTILE.INC"),
+    ("_xlfn.RANK.AVG", "COM.MICROSOFT.RANK.AVG"),
+    ("_xlfn.RANK.EQ", "COM.MICROSOFT.RANK.EQ"),
+    ("_xlfn.STDEV.P", "COM.MICROSOFT.STDEV.P"),
+    ("_xlfn.STDEV.S", "COM.MICROSOFT.STDEV.S"),
+    ("_xlfn.SWITCH", "COM.MICROSOFT.SWITCH"),
+    ("_xlfn.T.DIST", "COM.MICROSOFT.T.DIST"),
+    ("_xlfn.T.DIST.2T", "COM.MICROSOFT.T.DIST.2T"),
+    ("_xlfn.T.DIST.RT", "COM.MICROSOFT.T.DIST.RT"),
+    ("_xlfn.T.INV", "COM.MICROSOFT.T.INV"),
+    ("_xlfn.T.INV.2T", "COM.MICROSOFT.T.INV.2T"),
+    ("_xlfn.T.TEST", "COM.MICROSOFT.T.TEST"),
+    ("_xlfn.TEXTJOIN", "COM.MICROSOFT.TEXTJOIN"),
+    ("_xlfn.VAR.P", "COM.MICROSOFT.VAR.P"),
+    ("_xlfn.VAR.S", "COM.MICROSOFT.VAR.S"),
+    ("_xlfn.WEIBULL.DIST", "COM.MICROSOFT.WEIBULL.DIST"),
+    ("_xlfn.Z.TEST", "COM.MICROSOFT.Z.TEST"),
+    ("_xlfn._xlws.FILTER", "COM.MICROSOFT.FILTER"),
+    ("_xlfn._xlws.SORT", "COM.MICROSOFT.SORT"),
+    ("_xlfn.SKEW.P", "SKEWP"),
+    ("CEILING", "COM.MICROSOFT.CEILING"),
+    ("FLOOR", "COM.MICROSOFT.FLOOR"),
+];
+
+/// Functions that Excel stores with the `_xlfn.` prefix and that OpenFormula
+/// defines with the same name.
+///
+/// Other `_xlfn.` functions are placed in the `COM.MICROSOFT.` namespace, so
+/// they get their prefix back when they are converted to Excel again.
+const FUTURE_FUNCTIONS: &[&str] = &[
+    "ACOT",
+    "ACOTH",
+    "ARABIC",
+    "BASE",
+    "BITAND",
+    "BITLSHIFT",
+    "BITOR",
+    "BITRSHIFT",
+    "BITXOR",
+    "COMBINA",
+    "COT",
+    "COTH",
+    "CSC",
+    "CSCH",
+    "DAYS",
+    "DECIMAL",
+    "GAMMA",
+    "GAUSS",
+    "IFNA",
+    "ISFORMULA",
+    "ISOWEEKNUM",
+    "NUMBERVALUE",
+    "PDURATION",
+    "PERMUTATIONA",
+    "PHI",
+    "RRI",
+    "SEC",
+    "SECH",
+    "SHEET",
+    "SHEETS",
+    "UNICHAR",
+    "UNICODE",
+    "XOR",
+];
+
+/// Returns the OpenFormula name of an Excel function.
+///
+/// Example
+/// ```rust
+/// use open_formula::ooxml::function_from_excel;
+/// assert_eq!(function_from_excel("sum"), "SUM");
+/// assert_eq!(function_from_excel("_xlfn.STDEV.S"), "COM.MICROSOFT.STDEV.S");
+/// assert_eq!(function_from_excel("_xlfn.IFNA"), "IFNA");
+/// assert_eq!(function_from_excel("_xlfn.XLOOKUP"), "COM.MICROSOFT.XLOOKUP");
+/// ```
+pub fn function_from_excel(name: &str) -> String {
+    let name = name.to_uppercase();
+    if let Some((_, odf)) = FUNCTIONS
+        .iter()
+        .find(|(excel, _)| excel.eq_ignore_ascii_case(&name))
+    {
+        return odf.to_string();
+    }
+    match ["_XLFN._XLWS.", "_XLFN.", "_XLWS."]
+        .iter()
+        .find_map(|p| name.strip_prefix(p))
+    {
+        Some(f) if FUTURE_FUNCTIONS.contains(&f) => f.to_string(),
+        Some(f) => format!("COM.MICROSOFT.{}", f),
+        None => name,
+    }
+}
+
+/// Returns the Excel name of an OpenFormula function.
+///
+/// Example
+/// ```rust
+/// use open_formula::ooxml::function_to_excel;
+/// assert_eq!(function_to_excel("SUM"), "SUM");
+/// assert_eq!(function_to_excel("COM.MICROSOFT.CONCAT"), "_xlfn.CONCAT");
+/// assert_eq!(function_to_excel("FORMULA"), "_xlfn.FORMULATEXT");
+/// assert_eq!(function_to_excel("IFNA"), "_xlfn.IFNA");
+/// ```
+pub fn function_to_excel(name: &str) -> String {
+    let name = name.to_uppercase();
+    if let Some((excel, _)) = FUNCTIONS
+        .iter()
+        .find(|(_, odf)| odf.eq_ignore_ascii_case(&name))
+    {
+        return excel.to_string();
+    }
+    if FUTURE_FUNCTIONS.contains(&name.as_str()) {
+        return format!("_xlfn.{}", name);
+    }
+    match name.strip_prefix("COM.MICROSOFT.") {
+        Some(name) => format!("_xlfn.{}", name),
+        None => name,
+    }
+}
+
+/// Parses an Excel formula into an OpenFormula expression.
+///
+/// Example
+/// ```rust
+/// use open_formula::{ooxml::from_excel, types::*};
+/// assert_eq!(
+///     from_excel("=_xlfn.CONCAT(Sheet1!A1,\"x\")").unwrap(),
+///     Expr::Func("COM.MICROSOFT.CONCAT".into(), vec![
+///         Expr::Ref(Ref::cell(0, 0).on_sheet(SheetName::relative("Sheet1"))),
+///         Expr::String("x".into()),
+///     ])
+/// );
+/// ```
+pub fn from_excel(formula: &str) -> Result<Expr, Vec<Rich<'_, char>>> {
+    parser_with_config(ParserConfig::excel())
+        .parse(formula)
+        .into_result()
+        .map(|expr| rename_functions(expr, &function_from_excel))
+}
+
+/// Returns the Excel formula of an OpenFormula expression, without the leading `=`.
+///
+/// Returns an error for the first part of the expression that can't be written in Excel
+/// syntax.
+///
+/// Example
+/// ```rust
+/// use open_formula::{ooxml::{to_excel, ExcelError}, parser::{parser, Parser}};
+/// let expr = parser().parse("of:=COM.MICROSOFT.IFS([$Sheet2.A1]>0;1;TRUE();0)").unwrap();
+/// assert_eq!(to_excel(&expr).unwrap(), "_xlfn.IFS(Sheet2!A1>0,1,TRUE(),0)");
+/// let expr = parser().parse("SUM(A1:B2~C3)").unwrap();
+/// assert_eq!(to_excel(&expr), Err(ExcelError::Union));
+/// ```
+pub fn to_excel(expr: &Expr) -> Result<String, ExcelError> {
+    let config = ParserConfig::excel();
+    let unsupported = expr.fold(None, |err, e| {
+        err.or_else(|| match e {
+            Expr::RefIntersection(..) => Some(ExcelError::Intersection),
+            Expr::RefUnion(..) => Some(ExcelError::Union),
+            Expr::Ref(Ref::External(iri, _)) => Some(ExcelError::ExternalRef(iri.clone())),
+            // names are printed in the explicit form `$$'…'` unless they read back as names
+            Expr::Name(name) => match parser_with_config(config).parse(name).into_result() {
+                Ok(Expr::Name(n)) if n == *name => None,
+                _ => Some(ExcelError::Name(name.clone())),
+            },
+            Expr::Missing => Some(ExcelError::Missing),
+            _ => None,
+        })
+    });
+    match unsupported {
+        Some(err) => Err(err),
+        None => Ok(print_with_config(
+            &rename_functions(expr.clone(), &function_to_excel),
+            &config,
+        )),
+    }
+}
+
+fn rename_functions(expr: Expr, rename: &dyn Fn(&str) -> String) -> Expr {
+    expr.map(&mut |e| match e {
+        Expr::Func(f, args) => Expr::Func(rename(&f), args),
+        e => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        parser::parser,
+        types::{Ref, SheetName},
+    };
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            function_from_excel("_xlfn._xlws.SORT"),
+            "COM.MICROSOFT.SORT"
+        );
+        assert_eq!(function_to_excel("COM.MICROSOFT.SORT"), "_xlfn._xlws.SORT");
+        assert_eq!(function_from_excel("_xlfn.xor"), "XOR");
+        assert_eq!(function_to_excel("XOR"), "_xlfn.XOR");
+        assert_eq!(function_from_excel("_xlfn.SKEW.P"), "SKEWP");
+        assert_eq!(
+            function_from_excel("_xlfn.ceiling.math"),
+            "COM.MICROSOFT.CEILING.MATH"
+        );
+        assert_eq!(function_from_excel("CEILING"), "COM.MICROSOFT.CEILING");
+        assert_eq!(function_to_excel("com.microsoft.ceiling"), "CEILING");
+        assert_eq!(function_to_excel("COM.MICROSOFT.XLOOKUP"), "_xlfn.XLOOKUP");
+        assert_eq!(function_to_excel("ISOWEEKNUM"), "_xlfn.ISOWEEKNUM");
+    }
+
+    #[test]
+    fn excel_to_odf() {
+        assert_eq!(
+            from_excel("SUM(A1,B1)").unwrap(),
+            parser().parse("of:=SUM([.A1];[.B1])").unwrap()
+        );
+        assert_eq!(
+            from_excel("='Other Sheet'!$A$1:B2*1.5").unwrap(),
+            parser().parse("of:=['Other Sheet'.$A$1:.B2]*1.5").unwrap()
+        );
+        assert_eq!(
+            from_excel("_xlfn.STDEV.S({1,2;3,4})").unwrap(),
+            parser().parse("COM.MICROSOFT.STDEV.S({1;2|3;4})").unwrap()
+        );
+        assert_eq!(
+            from_excel("Sheet1:Sheet3!A1").unwrap(),
+            Expr::Ref(Ref::SheetRange(
+                SheetName::relative("Sheet1"),
+                SheetName::relative("Sheet3"),
+                Box::new(Ref::cell(0, 0))
+            ))
+        );
+        assert!(from_excel("SUM(A1;B1)").is_err());
+    }
+
+    #[test]
+    fn odf_to_excel() {
+        let odf = |f| to_excel(&parser().parse(f).unwrap()).unwrap();
+        assert_eq!(odf("of:=SUM([.A1];[.B1])"), "SUM(A1,B1)");
+        assert_eq!(odf("of:=[$'My Sheet'.A1:.B$2]"), "'My Sheet'!A1:B$2");
+        assert_eq!(odf("Sheet1.A1:Sheet3.B2"), "Sheet1:Sheet3!A1:B2");
+        assert_eq!(
+            odf("COM.MICROSOFT.TEXTJOIN(\",\";TRUE();{1|2})"),
+            "_xlfn.TEXTJOIN(\",\",TRUE(),{1;2})"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        for formula in [
+            "SUM(A1,B1)",
+            "IF(Sheet2!A1>=0,\"a\"\"b\",-1%)",
+            "_xlfn.CONCAT(\"x\",_xlfn.IFS(A1,1))",
+            "COUNT(A:A)*ROWS(1:3)",
+            // future functions outside the table of renamed functions
+            "_xlfn.XLOOKUP(A1,B:B,C:C)",
+            "_xlfn.IFNA(A1,0)+_xlfn.DAYS(B1,C1)",
+            "_xlfn._xlws.FILTER(A1:A9,B1:B9)",
+        ] {
+            assert_eq!(to_excel(&from_excel(formula).unwrap()).unwrap(), formula);
+        }
+    }
+
+    #[test]
+    fn unsupported() {
+        let odf = |f| to_excel(&parser().parse(f).unwrap());
+        assert_eq!(odf("SUM(A1:C4!B1:B5)"), Err(ExcelError::Intersection));
+        assert_eq!(odf("A1:C4!B1:B5~D1"), Err(ExcelError::Union));
+        assert_eq!(
+            odf("'file:///a.ods'#$Sheet1.A1+1"),
+            Err(ExcelError::ExternalRef("file:///a.ods".into()))
+        );
+        assert_eq!(
+            odf("$$'Tax Rate'*2"),
+            Err(ExcelError::Name("Tax Rate".into()))
+        );
+        assert_eq!(
+            to_excel(&Expr::Neg(Box::new(Expr::Missing))),
+            Err(ExcelError::Missing)
+        );
+        assert_eq!(odf("TaxRate*2"), Ok("TaxRate*2".into()));
+    }
+}
@@ -1,17 +1,204 @@
 //! A parser for OpenFormula [formula strings](https://docs.oasis-open.org/office/OpenDocument/v1.4/csd01/part4-formula/OpenDocument-v1.4-csd01-part4-formula.html#__RefHeading__1017930_715980110).
 
+use std::fmt::Display;
+
 use chumsky::pratt::*;
 use chumsky::prelude::*;
 pub use chumsky::Parser;
 
 use crate::{
     helpers::column_to_id,
-    types::{Comp, Expr, Ref},
+    types::{Comp, Coord, Error, Expr, Ref, SheetName, Spans},
     xmlchar::XmlChar,
 };
 
+/// Configuration of the formula syntax accepted by a parser.
+///
+/// The default configuration accepts OpenFormula as stored in OpenDocument
+/// files. Other configurations allow parsing formulas the way users type them
+/// into spreadsheet applications, e.g. with comma separated arguments or a
+/// decimal comma.
+///
+/// The argument and inline array separators must differ from the decimal
+/// separator, parsers of invalid configurations reject every formula with the
+/// error of [`ParserConfig::validate`].
+///
+/// The default limits exceed the [Basic Limits](https://docs.oasis-open.org/office/OpenDocument/v1.4/csd01/part4-formula/OpenDocument-v1.4-csd01-part4-formula.html#Basic_Limits)
+/// required by the specification and can be lowered for formulas from
+/// untrusted sources.
+///
+/// Example
+/// ```rust
+/// use open_formula::{parser::{Parser, ParserConfig, parser_with_config}, types::*};
+/// let config = ParserConfig {
+///     decimal_separator: ',',
+///     ..Default::default()
+/// };
+/// let res = parser_with_config(config).parse("SUM(1,5;2)");
+/// assert_eq!(res.unwrap(), Expr::Func("SUM".into(), vec![Expr::Num(1.5), Expr::Num(2.0)]));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserConfig {
+    /// Separator between function arguments, e.g. `;` in `SUM(1;2)`.
+    pub arg_separator: char,
+    /// Decimal point of numbers, e.g. `.` in `1.5`.
+    pub decimal_separator: char,
+    /// Separator between a sheet name and a cell, e.g. `.` in `Sheet1.A1`.
+    ///
+    /// The reference intersection operator `!` is not available if this is `!`.
+    pub sheet_separator: char,
+    /// Separator between the columns of an inline array, e.g. `;` in `{1;2}`.
+    pub array_column_separator: char,
+    /// Separator between the rows of an inline array, e.g. `|` in `{1|2}`.
+    pub array_row_separator: char,
+    /// Notation of cell references, e.g. `A1` or `R1C1`.
+    pub ref_style: RefStyle,
+    /// Maximum number of characters of a formula.
+    pub max_length: usize,
+    /// Maximum nesting depth of parentheses, function calls and prefix operators.
+    ///
+    /// Deeply nested formulas are rejected before parsing, as they could
+    /// overflow the stack of the recursive parser.
+    pub max_depth: usize,
+    /// Maximum number of binary and postfix operators an operand can be
+    /// nested in, e.g. 2 for `1` in `1+2*3+4`.
+    ///
+    /// Long chains of operators, e.g. `1+1+…+1`, form deep expression trees
+    /// that could overflow the stack when they are printed or evaluated.
+    pub max_operators: usize,
+    /// Maximum number of arguments of a function call.
+    pub max_args: usize,
+}
+
+/// Notation of cell references.
+///
+/// OpenDocument references in brackets, e.g. `[.A1]`, always use the A1 notation.
+///
+/// Example
+/// ```rust
+/// use open_formula::{eval::Context, parser::{Parser, ParserConfig, RefStyle, parser_with_config}, types::*};
+/// let ctx = Context {
+///     current_loc: Some((2, 4)),
+///     ..Default::default()
+/// };
+/// let config = ParserConfig {
+///     ref_style: RefStyle::R1C1 { origin: ctx.current_loc.unwrap_or_default() },
+///     ..ParserConfig::excel()
+/// };
+/// let res = parser_with_config(config).parse("R[-1]C+R1C1");
+/// assert_eq!(res.unwrap(), Expr::Add(
+///     Box::new(Expr::Ref(Ref::cell(2, 3))),
+///     Box::new(Expr::Ref(Ref::CellRef(Coord::absolute(0), Coord::absolute(0))))
+/// ));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefStyle {
+    /// Columns are letters and rows are numbers, e.g. `A1`, `$B$2` or `C:D`.
+    #[default]
+    A1,
+    /// Rows and columns are numbers, e.g. `R1C1` for an absolute reference or
+    /// `R[-1]C[2]` for a reference relative to the given origin.
+    ///
+    /// The origin is the column and row of the cell that contains the formula,
+    /// like [`Context::current_loc`](crate::eval::Context::current_loc).
+    R1C1 { origin: (usize, usize) },
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            arg_separator: ';',
+            decimal_separator: '.',
+            sheet_separator: '.',
+            array_column_separator: ';',
+            array_row_separator: '|',
+            ref_style: RefStyle::A1,
+            max_length: 8192,
+            max_depth: 64,
+            max_operators: 256,
+            max_args: 255,
+        }
+    }
+}
+
+impl ParserConfig {
+    /// Returns the configuration used by Excel in english locales, e.g.
+    /// `SUM(Sheet1!A1,1.5)` or `{1,2;3,4}`.
+    pub fn excel() -> Self {
+        Self {
+            arg_separator: ',',
+            decimal_separator: '.',
+            sheet_separator: '!',
+            array_column_separator: ',',
+            array_row_separator: ';',
+            ref_style: RefStyle::A1,
+            ..Self::default()
+        }
+    }
+
+    /// Checks that numbers, arguments and inline array elements can be told
+    /// apart.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::parser::{ConfigError, ParserConfig};
+    /// let config = ParserConfig {
+    ///     decimal_separator: ',',
+    ///     ..ParserConfig::excel()
+    /// };
+    /// assert_eq!(config.validate(), Err(ConfigError::DecimalSeparator(',')));
+    /// ```
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if [
+            self.arg_separator,
+            self.array_column_separator,
+            self.array_row_separator,
+        ]
+        .contains(&self.decimal_separator)
+        {
+            Err(ConfigError::DecimalSeparator(self.decimal_separator))
+        } else if self.array_column_separator == self.array_row_separator {
+            Err(ConfigError::ArraySeparator(self.array_row_separator))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An ambiguous parser configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The decimal separator also separates arguments or inline array
+    /// elements, e.g. `SUM(1,5)` could have one or two arguments.
+    DecimalSeparator(char),
+    /// Inline array columns and rows use the same separator.
+    ArraySeparator(char),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DecimalSeparator(c) => {
+                write!(f, "decimal separator '{}' is also used as a separator", c)
+            }
+            Self::ArraySeparator(c) => write!(
+                f,
+                "inline arrays use '{}' to separate both columns and rows",
+                c
+            ),
+        }
+    }
+}
+
 /// Returns a new OpenFormula parser.
 ///
+/// Incomplete formulas, e.g. while they are typed, still produce an
+/// expression alongside the errors. Missing or invalid operands and arguments
+/// are replaced by [`Expr::Missing`], e.g. `SUM(A1;` parses into
+/// `Func("SUM", [Ref(A1), Missing])` and `1+*2` into
+/// `Add(Num(1), Mul(Missing, Num(2)))`. Input after a complete formula, e.g.
+/// the second `)` of `SUM(1;2))`, is skipped.
+///
 /// Example
 /// ```rust
 /// use open_formula::{parser::{Parser, parser}, types::*};
@@ -19,130 +206,301 @@ use crate::{
 /// let res = parser().parse(input);
 /// assert_eq!(res.unwrap(), Expr::Sub(
 ///     Box::new(Expr::Add(
-///         Box::new(Expr::Ref(Ref::CellRef(0, 0))),
-///         Box::new(Expr::Ref(Ref::CellRef(1, 0)))
+///         Box::new(Expr::Ref(Ref::cell(0, 0))),
+///         Box::new(Expr::Ref(Ref::cell(1, 0)))
 ///     )),
 ///     Box::new(Expr::Num(5.0))
 /// ));
 /// ```
 pub fn parser<'a>() -> impl Parser<'a, &'a str, Expr, extra::Err<Rich<'a, char>>> {
-    recursive(|expr| {
-        let uppercase = any()
-            .filter(char::is_ascii_uppercase)
-            .repeated()
-            .at_least(1)
-            .to_slice();
-        let cellref = uppercase.then(text::digits(10).to_slice()).try_map(
-            |(col_chars, row_num): (&str, &str), span: SimpleSpan| {
-                let row = row_num
-                    .parse::<usize>()
-                    .map_err(|e| Rich::custom(span, format!("{}", e)))?
-                    .checked_sub(1)
-                    .ok_or(Rich::custom(
-                        span,
-                        "row reference must be greater than zero",
-                    ))?;
-                let col =
-                    column_to_id(col_chars).map_err(|e| Rich::custom(span, format!("{}", e)))?;
-                Ok(Expr::Ref(Ref::CellRef(col, row)))
-            },
-        );
-        let columnrange = uppercase.then_ignore(just(":")).then(uppercase).try_map(
-            |(a_chars, b_chars), span: SimpleSpan<usize>| {
-                let a = column_to_id(a_chars).map_err(|e| Rich::custom(span, format!("{}", e)))?;
-                let b = column_to_id(b_chars).map_err(|e| Rich::custom(span, format!("{}", e)))?;
-                Ok(Expr::Ref(Ref::ColumnRange(a, b)))
-            },
-        );
-        let rowrange = text::digits(10)
-            .to_slice()
+    parser_with_config(ParserConfig::default())
+}
+
+/// Returns a new parser that accepts the formula syntax of the given configuration.
+pub fn parser_with_config<'a>(
+    config: ParserConfig,
+) -> impl Parser<'a, &'a str, Expr, extra::Err<Rich<'a, char>>> {
+    spanned_parser_with_config(config).map(|(expr, _)| expr)
+}
+
+/// Returns a new OpenFormula parser that also returns the source spans of all
+/// parsed expressions.
+///
+/// Example
+/// ```rust
+/// use open_formula::{parser::{Parser, spanned_parser}, types::*};
+/// let input = "=SUM(A1; 2) * 3";
+/// let (expr, spans) = spanned_parser().parse(input).unwrap();
+/// assert_eq!(&input[spans.span.clone()], "SUM(A1; 2) * 3");
+/// assert_eq!(&input[spans.children[0].span.clone()], "SUM(A1; 2)");
+/// assert_eq!(&input[spans.children[0].children[1].span.clone()], "2");
+/// ```
+pub fn spanned_parser<'a>() -> impl Parser<'a, &'a str, (Expr, Spans), extra::Err<Rich<'a, char>>> {
+    spanned_parser_with_config(ParserConfig::default())
+}
+
+/// Returns a new parser that accepts the formula syntax of the given configuration
+/// and also returns the source spans of all parsed expressions.
+pub fn spanned_parser_with_config<'a>(
+    config: ParserConfig,
+) -> impl Parser<'a, &'a str, (Expr, Spans), extra::Err<Rich<'a, char>>> {
+    let expr = recursive(|expr| {
+        let style = config.ref_style;
+        let cellref = cell(style).map(|(col, row)| Expr::Ref(Ref::CellRef(col, row)));
+        let columnrange = column(style)
             .then_ignore(just(":"))
-            .then(text::digits(10).to_slice())
-            .try_map(|(a, b): (&str, &str), span: SimpleSpan<usize>| {
-                // TODO: refactor integer parsing into a function (DRY)
-                let int_a = a
-                    .parse::<usize>()
-                    .map_err(|e| Rich::custom(span, format!("{}", e)))?
-                    .checked_sub(1)
-                    .ok_or(Rich::custom(
-                        span,
-                        "row reference must be greater than zero",
-                    ))?;
-                let int_b = b
-                    .parse::<usize>()
-                    .map_err(|e| Rich::custom(span, format!("{}", e)))?
-                    .checked_sub(1)
-                    .ok_or(Rich::custom(
-                        span,
-                        "row reference must be greater than zero",
-                    ))?;
-                Ok(Expr::Ref(Ref::RowRange(int_a, int_b)))
+            .then(column(style))
+            .map(|(a, b)| Expr::Ref(Ref::ColumnRange(a, b).normalize()));
+        let rowrange = row(style)
+            .then_ignore(just(":"))
+            .then(row(style))
+            .map(|(a, b)| Expr::Ref(Ref::RowRange(a, b).normalize()));
+        let cellrange = cell(style)
+            .then_ignore(just(":"))
+            .then(cell(style))
+            .map(|(a, b)| Expr::Ref(Ref::CellRange(a, b).normalize()));
+
+        // OpenDocument references, e.g. [.A1], [.A1:.B2] or [$Sheet1.$A$1]
+        let odf_sheet_name =
+            sheet_name(none_of("]. #$'").repeated().at_least(1).collect::<String>());
+        let odf_sheet_locator = odf_sheet_name.or_not().then_ignore(just('.'));
+        // references into other documents, e.g. ['file:///data/rates.ods'#$Sheet1.A1]
+        let odf_ref = source()
+            .or_not()
+            .then(choice((
+                sheet_area(
+                    RefStyle::A1,
+                    odf_sheet_locator.clone(),
+                    odf_sheet_locator.clone(),
+                )
+                .map(Expr::Ref),
+                // references to deleted cells, e.g. [.#REF!]
+                odf_sheet_locator
+                    .or_not()
+                    .ignore_then(just("#REF!"))
+                    .to(Expr::Error(Error::Ref)),
+            )))
+            .delimited_by(just('['), just(']'))
+            .map(|(iri, expr)| match (iri, expr) {
+                (Some(iri), Expr::Ref(r)) => Expr::Ref(Ref::External(iri, Box::new(r))),
+                (_, expr) => expr,
             });
-        let cellrange = cellref.then_ignore(just(":")).then(cellref).map(|(a, b)| {
-            // FIXME: .as_cell_ref returns (&a0, &a1), which is not (a0, a1), can this be converted better?
-            let (a0, a1) = a.as_ref().unwrap().as_cell_ref().unwrap();
-            let (b0, b1) = b.as_ref().unwrap().as_cell_ref().unwrap();
-            Expr::Ref(Ref::CellRange((*a0, *a1), (*b0, *b1)))
-        });
+
+        // sheet qualified references, e.g. Sheet2.A1 or 'My Sheet'.B3:C9
+        let sheetref = sheet_qualified_ref(config).map(Expr::Ref);
+        let external = source()
+            .then(choice((
+                sheet_qualified_ref(config),
+                sheet_area(style, empty().to(None), empty().to(None)),
+            )))
+            .map(|(iri, r)| Expr::Ref(Ref::External(iri, Box::new(r))));
+
         // custom ident that differs from chumsky::text::ident, because a lot more
-        // characters are allowed
+        // characters are allowed; a leading underscore is accepted for prefixes
+        // like `_xlfn.` written by other producers
+        let ident_char = any().filter(|c: &char| {
+            c.is_xml_letter()
+                || c.is_xml_digit()
+                || *c == '_'
+                || *c == '.'
+                || c.is_xml_combining_char()
+        });
         let ident = any()
-            .filter(|c: &char| c.is_xml_letter())
-            .then(
-                any()
-                    .filter(|c: &char| {
-                        c.is_xml_letter()
-                            || c.is_xml_digit()
-                            || *c == '_'
-                            || *c == '.'
-                            || c.is_xml_combining_char()
-                    })
-                    .repeated(),
-            )
-            .padded()
-            .to_slice();
-        let num = text::int(10)
-            // TODO: make decimal point character configurable
-            .then(just('.').then(text::digits(10)).or_not())
+            .filter(|c: &char| c.is_xml_letter() || *c == '_')
+            .then(ident_char.repeated())
             .to_slice()
-            .from_str()
-            .unwrapped()
-            .map(Expr::Num);
-        let str_ = none_of('"')
+            .padded();
+
+        // named expressions, e.g. `TaxRate` or `$$'Tax Rate'`; identifiers that
+        // only start like a cell reference (e.g. `A1B`) are names as well
+        let quoted_name = none_of('\'')
+            .or(just("''").to('\''))
             .repeated()
-            .to_slice()
-            .map(|s: &str| Expr::String(s.to_string()))
-            .delimited_by(just('"'), just('"'));
+            .collect::<String>()
+            .delimited_by(just('\''), just('\''));
+        let name = choice((
+            just("$$").ignore_then(quoted_name.or(ident.map(str::to_string))),
+            // invalid references like `A0` are not names
+            cell_like(style)
+                .then(ident_char.not())
+                .not()
+                .ignore_then(ident.map(str::to_string)),
+        ))
+        .map(Expr::Name);
+        let cellref = cellref.then_ignore(ident_char.not());
+        let exponent = one_of("eE")
+            .then(one_of("+-").or_not())
+            .then(text::digits(10));
+        let decimal_separator = just(config.decimal_separator);
+        let number = choice((
+            text::digits(10)
+                .then(decimal_separator.then(text::digits(10)).or_not())
+                .ignored(),
+            decimal_separator.then(text::digits(10)).ignored(),
+        ))
+        .then(exponent.or_not())
+        .to_slice()
+        .try_map(move |n: &str, span: SimpleSpan| {
+            let n = n
+                .replace(config.decimal_separator, ".")
+                .parse::<f64>()
+                .map_err(|e| Rich::custom(span, format!("{}", e)))?;
+            if n.is_finite() {
+                Ok(n)
+            } else {
+                Err(Rich::custom(span, "number is out of range"))
+            }
+        });
+        let num = number.map(Expr::Num);
+        // quotes inside strings are escaped by doubling them
+        let str_chars = none_of('"')
+            .or(just("\"\"").to('"'))
+            .repeated()
+            .collect::<String>();
+        let str_ = str_chars
+            .delimited_by(just('"'), just('"'))
+            // a string that is cut off at the end of the input, e.g. `"abc`
+            .recover_with(via_parser(
+                just('"').ignore_then(str_chars).then_ignore(end().rewind()),
+            ))
+            .map(Expr::String);
+        // parameters may be omitted, e.g. `IF(A1;;0)`
+        let arg = empty()
+            .map_with(|_, e| (Expr::Empty, node(e.span(), vec![])))
+            .padded()
+            .then_ignore(one_of([config.arg_separator, ')']).rewind())
+            .or(expr.clone());
+        let args = arg
+            .separated_by(just(config.arg_separator))
+            .collect::<Vec<_>>()
+            .map(|args: Vec<Spanned>| match &args[..] {
+                // `F()` has no parameters rather than a single omitted one
+                [(Expr::Empty, _)] => vec![],
+                _ => args,
+            });
         let call = ident
             .then(
-                expr.clone()
-                    .separated_by(just(';'))
-                    .allow_trailing()
-                    .collect::<Vec<_>>()
-                    .delimited_by(just('('), just(')')),
+                args.clone()
+                    .delimited_by(just('('), just(')'))
+                    // a call that is cut off at the end of the input, e.g. `SUM(A1;`
+                    .recover_with(via_parser(
+                        just('(').ignore_then(args).then_ignore(end().rewind()),
+                    ))
+                    .validate(move |args: Vec<Spanned>, e, emitter| {
+                        if args.len() > config.max_args {
+                            emitter.emit(Rich::custom(
+                                e.span(),
+                                format!("function has more than {} arguments", config.max_args),
+                            ));
+                        }
+                        args
+                    }),
             )
-            .map(|(f, args): (&str, _)| Expr::Func(f.to_string(), args));
+            .map_with(|(f, args): (&str, Vec<Spanned>), e| {
+                let (args, children) = args.into_iter().unzip();
+                (Expr::Func(f.to_string(), args), node(e.span(), children))
+            });
+        let error = choice((
+            just("#NULL!").to(Error::Null),
+            just("#DIV/0!").to(Error::Div0),
+            just("#VALUE!").to(Error::Value),
+            just("#REF!").to(Error::Ref),
+            just("#NAME?").to(Error::Name),
+            just("#NUM!").to(Error::Num),
+            just("#N/A").to(Error::NotAvailable),
+        ))
+        .map(Expr::Error);
         let bool = choice((
             text::keyword("TRUE").to(Expr::Bool(true)),
             text::keyword("FALSE").to(Expr::Bool(false)),
         ))
         .padded();
 
+        // inline arrays only contain constants, rows are separated by "|"
+        #[cfg(feature = "large")]
+        let array = choice((
+            just('-')
+                .or_not()
+                .then(number)
+                .map(|(neg, n)| Expr::Num(if neg.is_some() { -n } else { n })),
+            str_,
+            error,
+            bool.clone(),
+        ))
+        .map_with(|expr, e| (expr, node(e.span(), vec![])))
+        .padded()
+        .separated_by(just(config.array_column_separator))
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .separated_by(just(config.array_row_separator))
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .delimited_by(just('{'), just('}'))
+        .try_map(|rows: Vec<Vec<Spanned>>, span| {
+            if rows.iter().all(|row| row.len() == rows[0].len()) {
+                let mut children = vec![];
+                let rows = rows
+                    .into_iter()
+                    .map(|row| {
+                        row.into_iter()
+                            .map(|(expr, spans)| {
+                                children.push(spans);
+                                expr
+                            })
+                            .collect()
+                    })
+                    .collect();
+                Ok((Expr::Array(rows), node(span, children)))
+            } else {
+                Err(Rich::custom(
+                    span,
+                    "all rows of an inline array must have the same length",
+                ))
+            }
+        });
+
+        let stop: Vec<char> = "+-*/^&=<>:!~%)"
+            .chars()
+            .chain([
+                config.arg_separator,
+                config.array_column_separator,
+                config.array_row_separator,
+            ])
+            .collect();
         // FIXME: check for proper order of choices
         let atom = choice((
-            rowrange,
-            expr.delimited_by(just('('), just(')')),
-            num,
-            str_,
-            columnrange,
-            cellrange,
+            odf_ref.map_with(|expr, e| (expr, node(e.span(), vec![]))),
+            #[cfg(feature = "large")]
+            array,
+            rowrange.map_with(|expr, e| (expr, node(e.span(), vec![]))),
+            // parentheses are part of the span of the enclosed expression
+            expr.clone()
+                .delimited_by(just('('), just(')'))
+                .recover_with(via_parser(
+                    just('(').ignore_then(expr).then_ignore(end().rewind()),
+                ))
+                .map_with(|(expr, spans): Spanned, e| (expr, node(e.span(), spans.children))),
+            choice((num, str_, error, external, sheetref, columnrange, cellrange))
+                .map_with(|expr, e| (expr, node(e.span(), vec![]))),
             call,
-            bool,
-            cellref,
+            choice((bool, cellref, name)).map_with(|expr, e| (expr, node(e.span(), vec![]))),
+        ))
+        // operands that are missing or invalid, e.g. in `1+` or `IF(A1>;1;2)`,
+        // are skipped up to the next separator, closing parenthesis or operator
+        .recover_with(via_parser(
+            none_of(stop.clone())
+                .repeated()
+                .then(one_of(stop).ignored().or(end()).rewind())
+                .map_with(|_, e| (Expr::Missing, node(e.span(), vec![]))),
         ))
         .padded();
 
-        let op = |c| just(c).padded();
+        let op = |c| {
+            just(c)
+                .map_with(|_, e| e.span())
+                .labelled("operator")
+                .padded()
+        };
 
         // all pieces are defined, the root of the parser starts here 🐉
         let comp = choice((
@@ -152,28 +510,460 @@ pub fn parser<'a>() -> impl Parser<'a, &'a str, Expr, extra::Err<Rich<'a, char>>
             just("=").to(Comp::Equal),
             just(">").to(Comp::Greater),
             just("<").to(Comp::Lower),
-        ));
+        ))
+        .labelled("operator");
 
         #[rustfmt::skip]
         let expr = atom.pratt((
-            prefix(7, op('-'), |_, rhs, _| Expr::Neg(Box::new(rhs))),
+            prefix(7, op('-'), |op, rhs, _| prefixed(Expr::Neg, op, rhs)),
             prefix(7, op('+'), |_, rhs, _| rhs),
-            postfix(6, op('%'), |lhs, _, _| Expr::Perc(Box::new(lhs))),
-            infix(left(10), op(':'), |l, _, r, _| Expr::Range(Box::new(l), Box::new(r))),
-            infix(left(9), op('!'), |l, _, r, _| Expr::RefIntersection(Box::new(l), Box::new(r))),
+            postfix(6, op('%'), |lhs, op, _| postfixed(Expr::Perc, lhs, op)),
+            infix(left(10), op(':'), |l, _, r, _| binary(Expr::Range, l, r)),
+            infix(left(9), op('!').filter(move |_| config.sheet_separator != '!'), |l, _, r, _| binary(Expr::RefIntersection, l, r)),
             #[cfg(feature = "medium")]
-            infix(left(8), op('~'), |l, _, r, _| Expr::RefUnion(Box::new(l), Box::new(r))),
-            infix(left(5), op('^'), |l, _, r, _| Expr::Pow(Box::new(l), Box::new(r))),
-            infix(left(4), op('*'), |l, _, r, _| Expr::Mul(Box::new(l), Box::new(r))),
-            infix(left(4), op('/'), |l, _, r, _| Expr::Div(Box::new(l), Box::new(r))),
-            infix(left(3), op('+'), |l, _, r, _| Expr::Add(Box::new(l), Box::new(r))),
-            infix(left(3), op('-'), |l, _, r, _| Expr::Sub(Box::new(l), Box::new(r))),
-            infix(left(2), op('&'), |l, _, r, _| Expr::Concat(Box::new(l), Box::new(r))),
-            infix(left(1), comp, |l, c, r, _| Expr::Cond(c, Box::new(l), Box::new(r)))
+            infix(left(8), op('~'), |l, _, r, _| binary(Expr::RefUnion, l, r)),
+            infix(left(5), op('^'), |l, _, r, _| binary(Expr::Pow, l, r)),
+            infix(left(4), op('*'), |l, _, r, _| binary(Expr::Mul, l, r)),
+            infix(left(4), op('/'), |l, _, r, _| binary(Expr::Div, l, r)),
+            infix(left(3), op('+'), |l, _, r, _| binary(Expr::Add, l, r)),
+            infix(left(3), op('-'), |l, _, r, _| binary(Expr::Sub, l, r)),
+            infix(left(2), op('&'), |l, _, r, _| binary(Expr::Concat, l, r)),
+            infix(left(1), comp, |l, c, r, _| binary(|a, b| Expr::Cond(c, a, b), l, r))
         ));
 
         expr
-    })
+    });
+
+    // the limits are checked upfront, so overly long or deeply nested input
+    // never reaches the recursive parser
+    let limits = any()
+        .repeated()
+        .to_slice()
+        .try_map(move |input: &str, _| {
+            config
+                .validate()
+                .map_err(|e| Rich::custom(SimpleSpan::from(0..0), e.to_string()))?;
+            check_limits(input, &config)
+        })
+        .rewind();
+
+    // formulas stored in OpenDocument files carry a namespace prefix, formulas
+    // typed by users usually start with "="
+    limits
+        .ignore_then(choice((just("of:="), just("="))).or_not().ignore_then(expr))
+        // unbalanced input after a complete formula, e.g. in `SUM(1;2))`
+        .then_ignore(end().recover_with(via_parser(any().repeated().then_ignore(end()))))
+}
+
+/// Checks the length and the nesting depth of a formula.
+///
+/// Nesting is counted lexically: each open parenthesis and each prefix
+/// operator is one level. Binary and postfix operators are counted separately
+/// until the enclosing parenthesis is closed or the next argument starts.
+/// Strings, quoted names and bracketed references are skipped.
+fn check_limits<'a>(input: &str, config: &ParserConfig) -> Result<(), Rich<'a, char>> {
+    if let Some((i, _)) = input.char_indices().nth(config.max_length) {
+        return Err(Rich::custom(
+            SimpleSpan::from(i..input.len()),
+            format!("formula is longer than {} characters", config.max_length),
+        ));
+    }
+    // prefix operators in front of each open parenthesis and the operators
+    // of the enclosing level
+    let mut parens: Vec<(usize, usize)> = vec![];
+    let mut depth = 0;
+    let mut prefix = 0;
+    // operators of the current level and of all enclosing levels
+    let mut level_operators = 0;
+    let mut operators = 0;
+    let mut operand_expected = true;
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                chars.by_ref().find(|(_, q)| *q == c);
+                operand_expected = false;
+            }
+            '[' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\'' => {
+                            chars.by_ref().find(|(_, q)| *q == '\'');
+                        }
+                        ']' => break,
+                        _ => {}
+                    }
+                }
+                operand_expected = false;
+            }
+            '(' => {
+                parens.push((prefix, level_operators));
+                depth += 1 + prefix;
+                prefix = 0;
+                level_operators = 0;
+                operand_expected = true;
+            }
+            ')' => {
+                operators -= level_operators;
+                level_operators = 0;
+                if let Some((p, o)) = parens.pop() {
+                    depth -= 1 + p;
+                    level_operators = o;
+                }
+                operand_expected = false;
+            }
+            '+' | '-' if operand_expected => prefix += 1,
+            '%' => {
+                level_operators += 1;
+                operators += 1;
+                operand_expected = false;
+            }
+            c if c.is_whitespace() => {}
+            c if c == config.arg_separator
+                || c == config.array_column_separator
+                || c == config.array_row_separator =>
+            {
+                operators -= level_operators;
+                level_operators = 0;
+                prefix = 0;
+                operand_expected = true;
+            }
+            c if "*/^&=<>:!~{}+-".contains(c) => {
+                // the second character of `<>`, `<=` and `>=` and the sheet
+                // separator `!` don't add an operation
+                let sheet = c == '!' && config.sheet_separator == '!';
+                if !operand_expected && !sheet && !"{}".contains(c) {
+                    level_operators += 1;
+                    operators += 1;
+                }
+                prefix = 0;
+                operand_expected = true;
+            }
+            _ => {
+                prefix = 0;
+                operand_expected = false;
+            }
+        }
+        if depth + prefix > config.max_depth {
+            return Err(Rich::custom(
+                SimpleSpan::from(i..i + c.len_utf8()),
+                format!("formula is nested deeper than {} levels", config.max_depth),
+            ));
+        }
+        if operators > config.max_operators {
+            return Err(Rich::custom(
+                SimpleSpan::from(i..i + c.len_utf8()),
+                format!("formula nests more than {} operators", config.max_operators),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// An expression together with its source spans.
+type Spanned = (Expr, Spans);
+
+fn node(span: SimpleSpan, children: Vec<Spans>) -> Spans {
+    Spans {
+        span: span.into_range(),
+        children,
+    }
+}
+
+// the spans of operations are derived from their operands and operators, so
+// they don't include surrounding whitespace
+
+fn prefixed(f: impl FnOnce(Box<Expr>) -> Expr, op: SimpleSpan, (a, a_spans): Spanned) -> Spanned {
+    let span = (op.start..a_spans.span.end).into();
+    (f(Box::new(a)), node(span, vec![a_spans]))
+}
+
+fn postfixed(f: impl FnOnce(Box<Expr>) -> Expr, (a, a_spans): Spanned, op: SimpleSpan) -> Spanned {
+    let span = (a_spans.span.start..op.end).into();
+    (f(Box::new(a)), node(span, vec![a_spans]))
+}
+
+fn binary(
+    f: impl FnOnce(Box<Expr>, Box<Expr>) -> Expr,
+    (l, l_spans): Spanned,
+    (r, r_spans): Spanned,
+) -> Spanned {
+    let span = (l_spans.span.start..r_spans.span.end).into();
+    (
+        f(Box::new(l), Box::new(r)),
+        node(span, vec![l_spans, r_spans]),
+    )
+}
+
+/// Returns a parser for a column coordinate, e.g. `A` or `$B` in A1 notation
+/// and `C2` or `C[-1]` in R1C1 notation.
+fn column<'a>(
+    style: RefStyle,
+) -> impl Parser<'a, &'a str, Coord, extra::Err<Rich<'a, char>>> + Clone {
+    match style {
+        RefStyle::A1 => just('$')
+            .or_not()
+            .then(
+                any()
+                    .filter(char::is_ascii_uppercase)
+                    .repeated()
+                    .at_least(1)
+                    .to_slice(),
+            )
+            .try_map(|(abs, col_chars): (_, &str), span: SimpleSpan| {
+                let id =
+                    column_to_id(col_chars).map_err(|e| Rich::custom(span, format!("{}", e)))?;
+                Ok(Coord {
+                    id,
+                    abs: abs.is_some(),
+                })
+            })
+            .boxed(),
+        RefStyle::R1C1 { origin } => just('C').ignore_then(r1c1_coord(origin.0)).boxed(),
+    }
+}
+
+/// Returns a parser for a row coordinate, e.g. `1` or `$2` in A1 notation
+/// and `R2` or `R[-1]` in R1C1 notation.
+fn row<'a>(style: RefStyle) -> impl Parser<'a, &'a str, Coord, extra::Err<Rich<'a, char>>> + Clone {
+    match style {
+        RefStyle::A1 => just('$')
+            .or_not()
+            .then(text::digits(10).to_slice())
+            .try_map(|(abs, row_num): (_, &str), span: SimpleSpan| {
+                Ok(Coord {
+                    id: row_to_id(row_num, span)?,
+                    abs: abs.is_some(),
+                })
+            })
+            .boxed(),
+        RefStyle::R1C1 { origin } => just('R').ignore_then(r1c1_coord(origin.1)).boxed(),
+    }
+}
+
+/// Returns a parser for the column and row of a cell, e.g. `B3` in A1 notation
+/// and `R3C2` in R1C1 notation.
+fn cell<'a>(
+    style: RefStyle,
+) -> impl Parser<'a, &'a str, (Coord, Coord), extra::Err<Rich<'a, char>>> + Clone {
+    match style {
+        RefStyle::A1 => column(style).then(row(style)).boxed(),
+        RefStyle::R1C1 { .. } => row(style)
+            .then(column(style))
+            .map(|(row, col)| (col, row))
+            .boxed(),
+    }
+}
+
+/// Returns a parser for anything shaped like a cell reference, e.g. `A0`,
+/// without checking that it lies inside the sheet.
+fn cell_like<'a>(
+    style: RefStyle,
+) -> impl Parser<'a, &'a str, (), extra::Err<Rich<'a, char>>> + Clone {
+    match style {
+        RefStyle::A1 => just('$')
+            .or_not()
+            .then(
+                any()
+                    .filter(char::is_ascii_uppercase)
+                    .repeated()
+                    .at_least(1),
+            )
+            .then(just('$').or_not())
+            .then(text::digits(10))
+            .ignored()
+            .boxed(),
+        RefStyle::R1C1 { .. } => {
+            let coord = choice((
+                text::digits(10).ignored(),
+                none_of(']').repeated().delimited_by(just('['), just(']')),
+            ))
+            .or_not();
+            just('R')
+                .then(coord)
+                .then(just('C'))
+                .then(coord)
+                .ignored()
+                .boxed()
+        }
+    }
+}
+
+/// Returns a parser for the number after `R` or `C` in R1C1 notation.
+///
+/// Plain numbers are absolute, offsets in brackets or a missing number are
+/// relative to the origin.
+fn r1c1_coord<'a>(
+    origin: usize,
+) -> impl Parser<'a, &'a str, Coord, extra::Err<Rich<'a, char>>> + Clone {
+    choice((
+        text::digits(10)
+            .to_slice()
+            .try_map(|n: &str, span| Ok(Coord::absolute(row_to_id(n, span)?))),
+        just('-')
+            .or_not()
+            .then(text::digits(10).to_slice())
+            .delimited_by(just('['), just(']'))
+            .try_map(move |(neg, n): (_, &str), span: SimpleSpan| {
+                let offset = n
+                    .parse::<usize>()
+                    .map_err(|e| Rich::custom(span, format!("{}", e)))?;
+                if neg.is_some() {
+                    origin.checked_sub(offset)
+                } else {
+                    origin.checked_add(offset)
+                }
+                .filter(|id| *id <= Coord::MAX_ID)
+                .map(Coord::relative)
+                .ok_or(Rich::custom(span, "reference is outside of the sheet"))
+            }),
+        empty().to(Coord::relative(origin)),
+    ))
+}
+
+/// Returns a parser for an optionally absolute sheet name, that is either
+/// quoted, e.g. `'My Sheet'`, or matches the given unquoted parser.
+fn sheet_name<'a, P>(
+    unquoted: P,
+) -> impl Parser<'a, &'a str, SheetName, extra::Err<Rich<'a, char>>> + Clone
+where
+    P: Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> + Clone,
+{
+    let quoted = none_of('\'')
+        .or(just("''").to('\''))
+        .repeated()
+        .collect::<String>()
+        .delimited_by(just('\''), just('\''));
+    just('$')
+        .or_not()
+        .then(quoted.or(unquoted))
+        .map(|(abs, name)| SheetName {
+            name,
+            abs: abs.is_some(),
+        })
+}
+
+/// Returns a parser for the document of an external reference, e.g.
+/// `'file:///data/rates.ods'#`, that yields the IRI of the document.
+pub(crate) fn source<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> + Clone {
+    none_of('\'')
+        .or(just("''").to('\''))
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .delimited_by(just('\''), just('\''))
+        .then_ignore(just('#'))
+}
+
+/// Returns a parser for a reference that names its sheet, e.g. `Sheet2.A1`,
+/// `'My Sheet'.B3:C9` or `Sheet1.A1:Sheet3.B2`.
+pub(crate) fn sheet_qualified_ref<'a>(
+    config: ParserConfig,
+) -> impl Parser<'a, &'a str, Ref, extra::Err<Rich<'a, char>>> + Clone {
+    let style = config.ref_style;
+    let plain_sheet_name = sheet_name(
+        any()
+            .filter(|c: &char| c.is_xml_letter() || c.is_xml_digit() || *c == '_')
+            .repeated()
+            .at_least(1)
+            .collect::<String>(),
+    );
+    let sheet_separator = just(config.sheet_separator);
+    let no_sheet = empty().to(None);
+    choice((
+        sheet_area(
+            style,
+            plain_sheet_name
+                .clone()
+                .then_ignore(sheet_separator)
+                .map(Some),
+            plain_sheet_name
+                .clone()
+                .or_not()
+                .then_ignore(sheet_separator)
+                .or_not()
+                .map(Option::flatten),
+        ),
+        // sheet range prefix, e.g. Sheet1:Sheet3!A1
+        plain_sheet_name
+            .clone()
+            .then_ignore(just(':'))
+            .then(plain_sheet_name)
+            .then_ignore(sheet_separator)
+            .then(sheet_area(style, no_sheet.clone(), no_sheet))
+            .try_map(|((a, b), r), span| sheet_ref(Some(a), Some(b), r, span)),
+    ))
+}
+
+/// Returns a parser for a cell, column or row reference whose start and end
+/// are prefixed by the given sheet locators.
+pub(crate) fn sheet_area<'a, L, R>(
+    style: RefStyle,
+    first: L,
+    second: R,
+) -> impl Parser<'a, &'a str, Ref, extra::Err<Rich<'a, char>>> + Clone
+where
+    L: Parser<'a, &'a str, Option<SheetName>, extra::Err<Rich<'a, char>>> + Clone,
+    R: Parser<'a, &'a str, Option<SheetName>, extra::Err<Rich<'a, char>>> + Clone,
+{
+    let cell_ = first.clone().then(cell(style));
+    choice((
+        cell_
+            .clone()
+            .then_ignore(just(':'))
+            .then(second.clone().then(cell(style)))
+            .try_map(|((a, start), (b, end)), span| {
+                sheet_ref(a, b, Ref::CellRange(start, end).normalize(), span)
+            }),
+        cell_.try_map(|(sheet, (col, row)), span| {
+            sheet_ref(sheet, None, Ref::CellRef(col, row), span)
+        }),
+        first
+            .clone()
+            .then(column(style))
+            .then_ignore(just(':'))
+            .then(second.clone().then(column(style)))
+            .try_map(|((a, start), (b, end)), span| {
+                sheet_ref(a, b, Ref::ColumnRange(start, end).normalize(), span)
+            }),
+        first
+            .then(row(style))
+            .then_ignore(just(':'))
+            .then(second.then(row(style)))
+            .try_map(|((a, start), (b, end)), span| {
+                sheet_ref(a, b, Ref::RowRange(start, end).normalize(), span)
+            }),
+    ))
+}
+
+/// Wraps a reference into the sheets it addresses.
+///
+/// A range that names its end sheet also needs to name its start sheet.
+fn sheet_ref(
+    start: Option<SheetName>,
+    end: Option<SheetName>,
+    r: Ref,
+    span: SimpleSpan,
+) -> Result<Ref, Rich<'static, char>> {
+    match (start, end) {
+        (None, None) => Ok(r),
+        (Some(a), None) => Ok(Ref::SheetRef(a, Box::new(r))),
+        (Some(a), Some(b)) if a == b => Ok(Ref::SheetRef(a, Box::new(r))),
+        (Some(a), Some(b)) => Ok(Ref::SheetRange(a, b, Box::new(r))),
+        (None, Some(_)) => Err(Rich::custom(span, "range start must name a sheet")),
+    }
+}
+
+/// Converts a one-based row number into a zero-based row id.
+fn row_to_id(row_num: &str, span: SimpleSpan) -> Result<usize, Rich<'_, char>> {
+    row_num
+        .parse::<usize>()
+        .map_err(|e| Rich::custom(span, format!("{}", e)))?
+        .checked_sub(1)
+        .ok_or(Rich::custom(
+            span,
+            "row reference must be greater than zero",
+        ))
 }
 
 #[cfg(test)]
@@ -198,6 +988,20 @@ mod tests {
             parse("3.000000000000000001"),
             Expr::Num(3.000000000000000001)
         );
+        assert_eq!(parse("007"), Expr::Num(7.0));
+        assert_eq!(parse(".5"), Expr::Num(0.5));
+        assert_eq!(parse("1E10"), Expr::Num(1e10));
+        assert_eq!(parse("1e+10"), Expr::Num(1e10));
+        assert_eq!(parse("2.5e-3"), Expr::Num(0.0025));
+        assert_eq!(parse(".5E1"), Expr::Num(5.0));
+        assert_eq!(
+            parse("123456789012345678901234567890"),
+            Expr::Num(123456789012345678901234567890.0)
+        );
+        assert_eq!(parse("1E-400"), Expr::Num(0.0));
+        assert!(parser().parse("1E400").has_errors());
+        assert!(parser().parse("1E").has_errors());
+        assert!(parser().parse("1.").has_errors());
     }
 
     #[test]
@@ -210,35 +1014,712 @@ mod tests {
     fn simple_string() {
         assert_eq!(parse("\"3\""), Expr::String("3".into()));
         assert_eq!(parse("\"ABCDEFG\""), Expr::String("ABCDEFG".into()));
+        assert_eq!(parse("\"\""), Expr::String("".into()));
+        assert_eq!(parse("\"\"\"\""), Expr::String("\"".into()));
+        assert_eq!(
+            parse("\"He said \"\"hi\"\"\""),
+            Expr::String("He said \"hi\"".into())
+        );
+        assert!(parser().parse("\"A\"B\"").has_errors());
+    }
+
+    #[test]
+    fn simple_error() {
+        assert_eq!(parse("#NULL!"), Expr::Error(Error::Null));
+        assert_eq!(parse("#DIV/0!"), Expr::Error(Error::Div0));
+        assert_eq!(parse("#VALUE!"), Expr::Error(Error::Value));
+        assert_eq!(parse("#REF!"), Expr::Error(Error::Ref));
+        assert_eq!(parse("#NAME?"), Expr::Error(Error::Name));
+        assert_eq!(parse("#NUM!"), Expr::Error(Error::Num));
+        assert_eq!(parse("#N/A"), Expr::Error(Error::NotAvailable));
+        assert_eq!(parse("[.#REF!]"), Expr::Error(Error::Ref));
+        assert_eq!(
+            parse("IF(A1=0;#DIV/0!;A1)"),
+            Expr::Func(
+                "IF".into(),
+                vec![
+                    Expr::Cond(
+                        Comp::Equal,
+                        Box::new(Expr::Ref(Ref::cell(0, 0))),
+                        Box::new(Expr::Num(0.0))
+                    ),
+                    Expr::Error(Error::Div0),
+                    Expr::Ref(Ref::cell(0, 0))
+                ]
+            )
+        );
+        assert!(parser().parse("#FOO!").has_errors());
+    }
+
+    #[test]
+    fn simple_array() {
+        assert_eq!(parse("{1}"), Expr::Array(vec![vec![Expr::Num(1.0)]]));
+        assert_eq!(
+            parse("{1;2;3|4;5;6}"),
+            Expr::Array(vec![
+                vec![Expr::Num(1.0), Expr::Num(2.0), Expr::Num(3.0)],
+                vec![Expr::Num(4.0), Expr::Num(5.0), Expr::Num(6.0)],
+            ])
+        );
+        assert_eq!(
+            parse("{ -1 ; \"A\" | TRUE ; #N/A }"),
+            Expr::Array(vec![
+                vec![Expr::Num(-1.0), Expr::String("A".into())],
+                vec![Expr::Bool(true), Expr::Error(Error::NotAvailable)],
+            ])
+        );
+        assert_eq!(
+            parse("SUM({1|2})"),
+            Expr::Func(
+                "SUM".into(),
+                vec![Expr::Array(vec![
+                    vec![Expr::Num(1.0)],
+                    vec![Expr::Num(2.0)]
+                ])]
+            )
+        );
+        assert!(parser().parse("{}").has_errors());
+        assert!(parser().parse("{1;2|3}").has_errors());
+        assert!(parser().parse("{A1}").has_errors());
     }
 
     #[test]
     fn simple_cellref() {
-        assert_eq!(parse("A1"), Expr::Ref(Ref::CellRef(0, 0)));
-        assert_eq!(parse("XY23"), Expr::Ref(Ref::CellRef(648, 22)));
+        assert_eq!(parse("A1"), Expr::Ref(Ref::cell(0, 0)));
+        assert_eq!(parse("XY23"), Expr::Ref(Ref::cell(648, 22)));
+    }
+
+    #[test]
+    fn absolute_cellref() {
+        assert_eq!(
+            parse("$A$1"),
+            Expr::Ref(Ref::CellRef(Coord::absolute(0), Coord::absolute(0)))
+        );
+        assert_eq!(
+            parse("$B3"),
+            Expr::Ref(Ref::CellRef(Coord::absolute(1), Coord::relative(2)))
+        );
+        assert_eq!(
+            parse("B$3"),
+            Expr::Ref(Ref::CellRef(Coord::relative(1), Coord::absolute(2)))
+        );
+        assert_eq!(
+            parse("$A:B"),
+            Expr::Ref(Ref::ColumnRange(Coord::absolute(0), Coord::relative(1)))
+        );
+        assert_eq!(
+            parse("1:$2"),
+            Expr::Ref(Ref::RowRange(Coord::relative(0), Coord::absolute(1)))
+        );
+        assert_eq!(
+            parse("A$1:$B2"),
+            Expr::Ref(Ref::CellRange(
+                (Coord::relative(0), Coord::absolute(0)),
+                (Coord::absolute(1), Coord::relative(1))
+            ))
+        );
+        // explicit named expression
+        assert_eq!(parse("$$A1"), Expr::Name("A1".into()));
     }
 
     #[test]
     fn simple_columnrange() {
-        assert_eq!(parse("A:A"), Expr::Ref(Ref::ColumnRange(0, 0)));
-        assert_eq!(parse("H:H"), Expr::Ref(Ref::ColumnRange(7, 7)));
-        assert_eq!(parse("B:AB"), Expr::Ref(Ref::ColumnRange(1, 27)));
+        assert_eq!(parse("A:A"), Expr::Ref(Ref::column_range(0, 0)));
+        assert_eq!(parse("H:H"), Expr::Ref(Ref::column_range(7, 7)));
+        assert_eq!(parse("B:AB"), Expr::Ref(Ref::column_range(1, 27)));
     }
 
     #[test]
     fn simple_rowrange() {
-        assert_eq!(parse("3:3"), Expr::Ref(Ref::RowRange(2, 2)));
-        assert_eq!(parse("1:5"), Expr::Ref(Ref::RowRange(0, 4)));
-        assert_eq!(parse("21:9"), Expr::Ref(Ref::RowRange(20, 8)));
+        assert_eq!(parse("3:3"), Expr::Ref(Ref::row_range(2, 2)));
+        assert_eq!(parse("1:5"), Expr::Ref(Ref::row_range(0, 4)));
+        assert_eq!(parse("21:9"), Expr::Ref(Ref::row_range(20, 8)));
+        assert_eq!(parse("21:9"), parse("9:21"));
+        assert_eq!(parse("B5:A1"), parse("A1:B5"));
+        assert_eq!(parse("Sheet2.$B1:A$5"), parse("Sheet2.A1:$B$5"));
     }
 
     #[test]
     fn simple_cellrange() {
-        assert_eq!(parse("A1:Z99"), Expr::Ref(Ref::CellRange((0, 0), (25, 98))));
+        assert_eq!(
+            parse("A1:Z99"),
+            Expr::Ref(Ref::cell_range((0, 0), (25, 98)))
+        );
         assert_eq!(
             parse("AA23:BB42"),
-            Expr::Ref(Ref::CellRange((26, 22), (53, 41)))
+            Expr::Ref(Ref::cell_range((26, 22), (53, 41)))
+        );
+    }
+
+    #[test]
+    fn odf_ref() {
+        assert_eq!(parse("[.A1]"), Expr::Ref(Ref::cell(0, 0)));
+        assert_eq!(
+            parse("[.$B$3]"),
+            Expr::Ref(Ref::CellRef(Coord::absolute(1), Coord::absolute(2)))
+        );
+        assert_eq!(
+            parse("[$Sheet1.$A$1]"),
+            Expr::Ref(
+                Ref::CellRef(Coord::absolute(0), Coord::absolute(0))
+                    .on_sheet(SheetName::absolute("Sheet1"))
+            )
+        );
+        assert_eq!(
+            parse("['My ''Sheet'''.C2]"),
+            Expr::Ref(Ref::cell(2, 1).on_sheet(SheetName::relative("My 'Sheet'")))
+        );
+        assert_eq!(
+            parse("[.A1:.B2]"),
+            Expr::Ref(Ref::cell_range((0, 0), (1, 1)))
+        );
+        assert_eq!(
+            parse("[Sheet1.A1:Sheet1.B2]"),
+            Expr::Ref(Ref::cell_range((0, 0), (1, 1)).on_sheet(SheetName::relative("Sheet1")))
+        );
+        assert_eq!(
+            parse("[Sheet1.A1:.B2]"),
+            Expr::Ref(Ref::cell_range((0, 0), (1, 1)).on_sheet(SheetName::relative("Sheet1")))
+        );
+        assert_eq!(
+            parse("[Sheet1.A1:Sheet3.B2]"),
+            Expr::Ref(Ref::SheetRange(
+                SheetName::relative("Sheet1"),
+                SheetName::relative("Sheet3"),
+                Box::new(Ref::cell_range((0, 0), (1, 1)))
+            ))
+        );
+        assert_eq!(parse("[.A:.C]"), Expr::Ref(Ref::column_range(0, 2)));
+        assert_eq!(parse("[.1:.1]"), Expr::Ref(Ref::row_range(0, 0)));
+        assert!(parser().parse("[A1]").has_errors());
+        assert!(parser().parse("[.A0]").has_errors());
+        assert!(parser().parse("[.A1:Sheet2.B2]").has_errors());
+    }
+
+    #[test]
+    fn sheet_ref() {
+        assert_eq!(
+            parse("Sheet2.A1"),
+            Expr::Ref(Ref::cell(0, 0).on_sheet(SheetName::relative("Sheet2")))
+        );
+        assert_eq!(
+            parse("$Sheet2.$A$1"),
+            Expr::Ref(
+                Ref::CellRef(Coord::absolute(0), Coord::absolute(0))
+                    .on_sheet(SheetName::absolute("Sheet2"))
+            )
+        );
+        assert_eq!(
+            parse("'My Sheet'.B3:C9"),
+            Expr::Ref(Ref::cell_range((1, 2), (2, 8)).on_sheet(SheetName::relative("My Sheet")))
+        );
+        assert_eq!(
+            parse("Sheet1.A:B"),
+            Expr::Ref(Ref::column_range(0, 1).on_sheet(SheetName::relative("Sheet1")))
+        );
+        assert_eq!(
+            parse("Sheet1.1:3"),
+            Expr::Ref(Ref::row_range(0, 2).on_sheet(SheetName::relative("Sheet1")))
+        );
+        assert_eq!(
+            parse("Sheet1.A1:Sheet3.B2"),
+            Expr::Ref(Ref::SheetRange(
+                SheetName::relative("Sheet1"),
+                SheetName::relative("Sheet3"),
+                Box::new(Ref::cell_range((0, 0), (1, 1)))
+            ))
+        );
+        assert_eq!(
+            parse("SUM(Sheet1.A1;A2)"),
+            Expr::Func(
+                "SUM".into(),
+                vec![
+                    Expr::Ref(Ref::cell(0, 0).on_sheet(SheetName::relative("Sheet1"))),
+                    Expr::Ref(Ref::cell(0, 1))
+                ]
+            )
+        );
+        assert_eq!(
+            parse("ORG.OPENOFFICE.ERRORTYPE(A1)"),
+            Expr::Func(
+                "ORG.OPENOFFICE.ERRORTYPE".into(),
+                vec![Expr::Ref(Ref::cell(0, 0))]
+            )
+        );
+    }
+
+    #[test]
+    fn odf_formula() {
+        assert_eq!(
+            parse("of:=[.A1]+1"),
+            Expr::Add(
+                Box::new(Expr::Ref(Ref::cell(0, 0))),
+                Box::new(Expr::Num(1.0))
+            )
+        );
+        assert_eq!(
+            parse("of:=SUM([.A1:.A3])"),
+            Expr::Func(
+                "SUM".into(),
+                vec![Expr::Ref(Ref::cell_range((0, 0), (0, 2)))]
+            )
+        );
+    }
+
+    #[test]
+    fn config_excel() {
+        let parse = |input| {
+            parser_with_config(ParserConfig::excel())
+                .parse(input)
+                .unwrap()
+        };
+        assert_eq!(
+            parse("=SUM(A1,1.5)"),
+            Expr::Func(
+                "SUM".into(),
+                vec![Expr::Ref(Ref::cell(0, 0)), Expr::Num(1.5)]
+            )
+        );
+        assert_eq!(
+            parse("Sheet1!A1:B2"),
+            Expr::Ref(Ref::cell_range((0, 0), (1, 1)).on_sheet(SheetName::relative("Sheet1")))
+        );
+        assert_eq!(
+            parse("'My Sheet'!$A$1"),
+            Expr::Ref(
+                Ref::CellRef(Coord::absolute(0), Coord::absolute(0))
+                    .on_sheet(SheetName::relative("My Sheet"))
+            )
+        );
+        assert_eq!(
+            parse("Sheet1:Sheet3!A1"),
+            Expr::Ref(Ref::SheetRange(
+                SheetName::relative("Sheet1"),
+                SheetName::relative("Sheet3"),
+                Box::new(Ref::cell(0, 0))
+            ))
+        );
+        assert_eq!(
+            parse("{1,2;3,4}"),
+            Expr::Array(vec![
+                vec![Expr::Num(1.0), Expr::Num(2.0)],
+                vec![Expr::Num(3.0), Expr::Num(4.0)],
+            ])
+        );
+    }
+
+    #[test]
+    fn recovery() {
+        let recover = |input| {
+            let (expr, errors) = parser().parse(input).into_output_errors();
+            assert!(!errors.is_empty());
+            expr
+        };
+        assert_eq!(
+            recover("SUM(A1;"),
+            Some(Expr::Func(
+                "SUM".into(),
+                vec![Expr::Ref(Ref::cell(0, 0)), Expr::Missing]
+            ))
+        );
+        assert_eq!(
+            recover("IF(A1>"),
+            Some(Expr::Func(
+                "IF".into(),
+                vec![Expr::Cond(
+                    Comp::Greater,
+                    Box::new(Expr::Ref(Ref::cell(0, 0))),
+                    Box::new(Expr::Missing)
+                )]
+            ))
+        );
+        assert_eq!(
+            recover("1+ "),
+            Some(Expr::Add(Box::new(Expr::Num(1.0)), Box::new(Expr::Missing)))
+        );
+        assert_eq!(
+            recover("2*(1+A1"),
+            Some(Expr::Mul(
+                Box::new(Expr::Num(2.0)),
+                Box::new(Expr::Add(
+                    Box::new(Expr::Num(1.0)),
+                    Box::new(Expr::Ref(Ref::cell(0, 0)))
+                ))
+            ))
+        );
+        assert_eq!(recover("\"abc"), Some(Expr::String("abc".into())));
+        assert_eq!(recover(""), Some(Expr::Missing));
+        // invalid input in the middle of the formula
+        let missing = || Box::new(Expr::Missing);
+        let num = |n| Box::new(Expr::Num(n));
+        assert_eq!(
+            recover("IF(A1>;1;2)"),
+            Some(Expr::Func(
+                "IF".into(),
+                vec![
+                    Expr::Cond(
+                        Comp::Greater,
+                        Box::new(Expr::Ref(Ref::cell(0, 0))),
+                        missing()
+                    ),
+                    Expr::Num(1.0),
+                    Expr::Num(2.0)
+                ]
+            ))
+        );
+        assert_eq!(
+            recover("1+*2"),
+            Some(Expr::Add(
+                num(1.0),
+                Box::new(Expr::Mul(missing(), num(2.0)))
+            ))
+        );
+        assert_eq!(
+            recover("SUM(1;*;2)+A1"),
+            Some(Expr::Add(
+                Box::new(Expr::Func(
+                    "SUM".into(),
+                    vec![
+                        Expr::Num(1.0),
+                        Expr::Mul(missing(), missing()),
+                        Expr::Num(2.0)
+                    ]
+                )),
+                Box::new(Expr::Ref(Ref::cell(0, 0)))
+            ))
+        );
+        assert_eq!(
+            recover("SUM(1;2))"),
+            Some(Expr::Func(
+                "SUM".into(),
+                vec![Expr::Num(1.0), Expr::Num(2.0)]
+            ))
+        );
+        assert_eq!(
+            recover("1+?#2*3"),
+            Some(Expr::Add(
+                num(1.0),
+                Box::new(Expr::Mul(missing(), num(3.0)))
+            ))
+        );
+        // valid formulas don't produce placeholders
+        assert_eq!(parse("SUM()"), Expr::Func("SUM".into(), vec![]));
+        assert_eq!(
+            parse("SUM(1;)"),
+            Expr::Func("SUM".into(), vec![Expr::Num(1.0), Expr::Empty])
+        );
+    }
+
+    #[test]
+    fn external_refs() {
+        let external =
+            |r: Ref| Expr::Ref(Ref::External("file:///data/rates.ods".into(), Box::new(r)));
+        let sheet1 = SheetName {
+            name: "Sheet1".into(),
+            abs: true,
+        };
+        assert_eq!(
+            parse("'file:///data/rates.ods'#$Sheet1.A1"),
+            external(Ref::cell(0, 0).on_sheet(sheet1.clone()))
+        );
+        assert_eq!(
+            parse("['file:///data/rates.ods'#$Sheet1.A1]"),
+            external(Ref::cell(0, 0).on_sheet(sheet1.clone()))
+        );
+        assert_eq!(
+            parse("['file:///data/rates.ods'#$Sheet1.A1:.B2]"),
+            external(Ref::cell_range((0, 0), (1, 1)).on_sheet(sheet1))
+        );
+        assert_eq!(
+            parse("'file:///data/rates.ods'#B1:C2"),
+            external(Ref::cell_range((1, 0), (2, 1)))
+        );
+        assert_eq!(
+            parse("'it''s.ods'#A1"),
+            Expr::Ref(Ref::External("it's.ods".into(), Box::new(Ref::cell(0, 0))))
+        );
+        assert_eq!(
+            parse("['file:///data/rates.ods'#.#REF!]"),
+            Expr::Error(Error::Ref)
+        );
+        assert!(parser().parse("''#A1").has_errors());
+        assert!(parser().parse("'rates.ods'A1").has_errors());
+    }
+
+    #[test]
+    fn invalid_config() {
+        let config = ParserConfig {
+            decimal_separator: ',',
+            ..ParserConfig::excel()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::DecimalSeparator(',')));
+        let errors = parser_with_config(config).parse("SUM(1,5)").into_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "decimal separator ',' is also used as a separator"
+        );
+        let config = ParserConfig {
+            array_row_separator: ';',
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(ConfigError::ArraySeparator(';')));
+        assert!(parser_with_config(config).parse("{1;2}").has_errors());
+        assert_eq!(ParserConfig::default().validate(), Ok(()));
+        assert_eq!(ParserConfig::excel().validate(), Ok(()));
+    }
+
+    #[test]
+    fn limits() {
+        let config = ParserConfig {
+            max_length: 20,
+            max_depth: 3,
+            max_operators: 3,
+            max_args: 2,
+            ..Default::default()
+        };
+        let errors = |input: &str| {
+            parser_with_config(config)
+                .parse(input)
+                .into_errors()
+                .iter()
+                .map(|e| (e.to_string(), e.span().into_range()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(errors("SUM(1;2)+((1))*-(-1)"), vec![]);
+        assert_eq!(
+            errors("1+2+3+4+5+6+7+8+9+10+11"),
+            vec![("formula is longer than 20 characters".into(), 20..23)]
+        );
+        assert_eq!(
+            errors("((((1))))"),
+            vec![("formula is nested deeper than 3 levels".into(), 3..4)]
+        );
+        assert_eq!(
+            errors("-(--1)"),
+            vec![("formula is nested deeper than 3 levels".into(), 3..4)]
+        );
+        assert_eq!(errors("\"((((\"&[.A1]&$$'(('"), vec![]);
+        assert_eq!(
+            errors("1+2*3-4/5"),
+            vec![("formula nests more than 3 operators".into(), 7..8)]
+        );
+        assert_eq!(
+            errors("1%%%%"),
+            vec![("formula nests more than 3 operators".into(), 4..5)]
+        );
+        assert_eq!(errors("F(1+2;3+4)<>(5+6)*7"), vec![]);
+        assert_eq!(
+            errors("SUM(1;2;3)"),
+            vec![("function has more than 2 arguments".into(), 3..10)]
+        );
+
+        // the default limits guard the stack of the parser
+        let nested = format!("{}1{}", "F(".repeat(10000), ")".repeat(10000));
+        assert!(parser().parse(&nested).has_errors());
+        let negated = format!("{}1", "-".repeat(10000));
+        assert!(parser().parse(&negated).has_errors());
+        let chain = format!("1{}", "+1".repeat(4000));
+        assert!(parser().parse(&chain).has_errors());
+        // coordinates that don't fit a usize are errors, not overflows
+        assert!(parser().parse("ZZZZZZZZZZZZZZZ1").has_errors());
+        assert!(parser().parse("A99999999999999999999").has_errors());
+    }
+
+    #[test]
+    fn limits_stack() {
+        // formulas within the default limits can be parsed, printed and
+        // evaluated on a small stack
+        let run = |formula: String| {
+            std::thread::Builder::new()
+                .stack_size(2 << 20)
+                .spawn(move || {
+                    let expr = parser().parse(&formula).unwrap();
+                    assert!(!expr.to_string().is_empty());
+                    let ctx = crate::eval::Context::default();
+                    assert!(crate::eval::eval(&ctx, &expr).is_ok());
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        let nested = |inner: &str| format!("{}{inner}{}", "ABS(".repeat(63), ")".repeat(63));
+        run(nested(&format!("1{}", "+1".repeat(256))));
+        run(nested(&format!("1{}", "^1".repeat(256))));
+    }
+
+    #[test]
+    fn names() {
+        let name = |n: &str| Expr::Name(n.into());
+        assert_eq!(
+            parse("TaxRate*Net"),
+            Expr::Mul(Box::new(name("TaxRate")), Box::new(name("Net")))
+        );
+        assert_eq!(
+            parse("SUM(Sales)"),
+            Expr::Func("SUM".into(), vec![name("Sales")])
+        );
+        assert_eq!(parse("A1B"), name("A1B"));
+        assert_eq!(parse("TRUEX"), name("TRUEX"));
+        assert_eq!(parse("_my.name2"), name("_my.name2"));
+        assert_eq!(parse("$$'Tax Rate'"), name("Tax Rate"));
+        assert_eq!(parse("$$'It''s'"), name("It's"));
+        // names that look like a sheet qualified reference are references
+        assert_eq!(
+            parse("Sales.Q1"),
+            Expr::Ref(Ref::cell(16, 0).on_sheet(SheetName::relative("Sales")))
+        );
+        assert_eq!(parse("$$'Sales.Q1'"), name("Sales.Q1"));
+        assert_eq!(name("Tax.A1").to_string(), "$$'Tax.A1'");
+        assert_eq!(parse(&name("Tax.A1").to_string()), name("Tax.A1"));
+        assert_eq!(
+            parse("Sales:Costs"),
+            Expr::Range(Box::new(name("Sales")), Box::new(name("Costs")))
+        );
+        assert_eq!(parse("A1"), Expr::Ref(Ref::cell(0, 0)));
+        assert!(parser().parse("A0").has_errors());
+        assert!(parser().parse("Tax Rate").has_errors());
+    }
+
+    #[test]
+    fn empty_params() {
+        assert_eq!(
+            parse("IF(A1;;0)"),
+            Expr::Func(
+                "IF".into(),
+                vec![Expr::Ref(Ref::cell(0, 0)), Expr::Empty, Expr::Num(0.0)]
+            )
+        );
+        assert_eq!(
+            parse("ADDRESS(1;1; ;FALSE)"),
+            Expr::Func(
+                "ADDRESS".into(),
+                vec![
+                    Expr::Num(1.0),
+                    Expr::Num(1.0),
+                    Expr::Empty,
+                    Expr::Bool(false)
+                ]
+            )
+        );
+        assert_eq!(
+            parse("F(;)"),
+            Expr::Func("F".into(), vec![Expr::Empty, Expr::Empty])
+        );
+        assert_eq!(
+            parse("F(\"\";0)"),
+            Expr::Func("F".into(), vec![Expr::String("".into()), Expr::Num(0.0)])
+        );
+    }
+
+    #[test]
+    fn spans() {
+        let text = |input: &'static str| {
+            let (_, spans) = spanned_parser().parse(input).unwrap();
+            fn collect<'a>(input: &'a str, spans: &Spans, out: &mut Vec<&'a str>) {
+                out.push(&input[spans.span.clone()]);
+                for child in &spans.children {
+                    collect(input, child, out);
+                }
+            }
+            let mut out = vec![];
+            collect(input, &spans, &mut out);
+            out
+        };
+        assert_eq!(text("of:=[.A1]"), vec!["[.A1]"]);
+        assert_eq!(
+            text(" 1 + -A1% * 2 "),
+            vec!["1 + -A1% * 2", "1", "-A1% * 2", "-A1%", "-A1", "A1", "2"]
+        );
+        assert_eq!(
+            text("(1 + 2) * 3"),
+            vec!["(1 + 2) * 3", "(1 + 2)", "1", "2", "3"]
+        );
+        assert_eq!(
+            text("IF(A1 = 0; \"x\"; Sheet2.B1:B3)"),
+            vec![
+                "IF(A1 = 0; \"x\"; Sheet2.B1:B3)",
+                "A1 = 0",
+                "A1",
+                "0",
+                "\"x\"",
+                "Sheet2.B1:B3"
+            ]
+        );
+        assert_eq!(
+            text("{1; -2|#N/A;TRUE}"),
+            vec!["{1; -2|#N/A;TRUE}", "1", "-2", "#N/A", "TRUE"]
+        );
+    }
+
+    #[test]
+    fn config_r1c1() {
+        let config = ParserConfig {
+            ref_style: RefStyle::R1C1 { origin: (2, 4) },
+            ..ParserConfig::excel()
+        };
+        let parse = |input| parser_with_config(config).parse(input).into_result();
+        let abs = |col, row| Ref::CellRef(Coord::absolute(col), Coord::absolute(row));
+        assert_eq!(parse("R1C1"), Ok(Expr::Ref(abs(0, 0))));
+        assert_eq!(parse("RC"), Ok(Expr::Ref(Ref::cell(2, 4))));
+        assert_eq!(parse("R[-1]C"), Ok(Expr::Ref(Ref::cell(2, 3))));
+        assert_eq!(parse("RC[2]"), Ok(Expr::Ref(Ref::cell(4, 4))));
+        assert_eq!(
+            parse("R2C[-2]"),
+            Ok(Expr::Ref(Ref::CellRef(
+                Coord::relative(0),
+                Coord::absolute(1)
+            )))
+        );
+        assert_eq!(
+            parse("SUM(R1C1:R[1]C[1])"),
+            Ok(Expr::Func(
+                "SUM".into(),
+                vec![Expr::Ref(Ref::CellRange(
+                    (Coord::absolute(0), Coord::absolute(0)),
+                    (Coord::relative(3), Coord::relative(5))
+                ))]
+            ))
+        );
+        assert_eq!(
+            parse("C1:C[1]"),
+            Ok(Expr::Ref(Ref::ColumnRange(
+                Coord::absolute(0),
+                Coord::relative(3)
+            )))
+        );
+        assert_eq!(
+            parse("R:R3"),
+            Ok(Expr::Ref(Ref::RowRange(
+                Coord::absolute(2),
+                Coord::relative(4)
+            )))
+        );
+        assert_eq!(
+            parse("Sheet2!R1C1"),
+            Ok(Expr::Ref(abs(0, 0).on_sheet(SheetName::relative("Sheet2"))))
+        );
+        // bracketed OpenDocument references stay in A1 notation
+        assert_eq!(parse("[.A1]"), Ok(Expr::Ref(Ref::cell(0, 0))));
+        assert_eq!(parse("A1"), Ok(Expr::Name("A1".into())));
+        assert!(parse("R0C1").is_err());
+        assert!(parse("R[-5]C").is_err());
+    }
+
+    #[test]
+    fn config_decimal_comma() {
+        let config = ParserConfig {
+            decimal_separator: ',',
+            ..Default::default()
+        };
+        let parse = |input| parser_with_config(config).parse(input).unwrap();
+        assert_eq!(parse("1,5"), Expr::Num(1.5));
+        assert_eq!(parse(",5e2"), Expr::Num(50.0));
+        assert_eq!(
+            parse("ROUND(2,25;1)"),
+            Expr::Func("ROUND".into(), vec![Expr::Num(2.25), Expr::Num(1.0)])
         );
+        assert!(parser_with_config(config).parse("1.5").has_errors());
     }
 
     #[test]
@@ -291,22 +1772,22 @@ mod tests {
         assert_eq!(
             parse("B4:B5:C5"),
             Expr::Range(
-                Box::new(Expr::Ref(Ref::CellRange((1, 3), (1, 4)))),
-                Box::new(Expr::Ref(Ref::CellRef(2, 4)))
+                Box::new(Expr::Ref(Ref::cell_range((1, 3), (1, 4)))),
+                Box::new(Expr::Ref(Ref::cell(2, 4)))
             )
         );
         assert_eq!(
             parse("A1:C4!B1:B5"),
             Expr::RefIntersection(
-                Box::new(Expr::Ref(Ref::CellRange((0, 0), (2, 3)))),
-                Box::new(Expr::Ref(Ref::CellRange((1, 0), (1, 4))))
+                Box::new(Expr::Ref(Ref::cell_range((0, 0), (2, 3)))),
+                Box::new(Expr::Ref(Ref::cell_range((1, 0), (1, 4))))
             )
         );
         assert_eq!(
             parse("A1:B2~B2:C3"),
             Expr::RefUnion(
-                Box::new(Expr::Ref(Ref::CellRange((0, 0), (1, 1)))),
-                Box::new(Expr::Ref(Ref::CellRange((1, 1), (2, 2))))
+                Box::new(Expr::Ref(Ref::cell_range((0, 0), (1, 1)))),
+                Box::new(Expr::Ref(Ref::cell_range((1, 1), (2, 2))))
             )
         );
     }
@@ -0,0 +1,494 @@
+//! Conversion of OpenFormula expressions back into formula strings.
+
+use crate::{
+    helpers::id_to_column,
+    parser::{parser_with_config, Parser, ParserConfig, RefStyle},
+    types::{Comp, Coord, Expr, Ref, SheetName},
+    xmlchar::XmlChar,
+};
+
+/// Returns the formula string of an expression.
+///
+/// Example
+/// ```rust
+/// use open_formula::{printer::print, types::*};
+/// let expr = Expr::Mul(
+///     Box::new(Expr::Add(
+///         Box::new(Expr::Ref(Ref::cell(0, 0))),
+///         Box::new(Expr::Num(1.5))
+///     )),
+///     Box::new(Expr::Num(2.0))
+/// );
+/// assert_eq!(print(&expr), "(A1+1.5)*2");
+/// ```
+pub fn print(expr: &Expr) -> String {
+    print_with_config(expr, &ParserConfig::default())
+}
+
+/// Returns the formula string of an expression using the syntax of the given configuration.
+pub fn print_with_config(expr: &Expr, config: &ParserConfig) -> String {
+    print_expr(expr, config, &|r| print_ref(r, config))
+}
+
+/// Returns the formula string of an expression as stored in OpenDocument files.
+///
+/// The formula carries the `of:=` namespace prefix and references are enclosed in brackets.
+///
+/// Example
+/// ```rust
+/// use open_formula::{parser::{Parser, parser}, printer::print_odf};
+/// let expr = parser().parse("SUM('My Sheet'.A1:B2)*Sheet2.$C$3").unwrap();
+/// assert_eq!(print_odf(&expr), "of:=SUM(['My Sheet'.A1:.B2])*[Sheet2.$C$3]");
+/// ```
+pub fn print_odf(expr: &Expr) -> String {
+    format!(
+        "of:={}",
+        print_expr(expr, &ParserConfig::default(), &print_odf_ref)
+    )
+}
+
+type RefPrinter<'a> = dyn Fn(&Ref) -> String + 'a;
+
+fn print_expr(expr: &Expr, config: &ParserConfig, refs: &RefPrinter) -> String {
+    match expr {
+        Expr::Num(n) => print_num(*n, config),
+        Expr::Bool(b) => {
+            if *b {
+                "TRUE".into()
+            } else {
+                "FALSE".into()
+            }
+        }
+        Expr::String(s) => format!("\"{}\"", s.replace('"', "\"\"")),
+        // a range of two single cells would be read back as one cell range
+        Expr::Range(a, b) if !is_area(a) && precedence(b) == u8::MAX => format!(
+            "{}:({})",
+            operand(a, precedence(expr), config, refs),
+            print_expr(b, config, refs)
+        ),
+        Expr::Range(a, b) => infix(a, ":", b, expr, config, refs),
+        Expr::RefIntersection(a, b) => infix(a, "!", b, expr, config, refs),
+        Expr::RefUnion(a, b) => infix(a, "~", b, expr, config, refs),
+        Expr::Perc(a) => format!("{}%", operand(a, precedence(expr), config, refs)),
+        Expr::Neg(a) => format!("-{}", operand(a, precedence(expr), config, refs)),
+        Expr::Add(a, b) => infix(a, "+", b, expr, config, refs),
+        Expr::Sub(a, b) => infix(a, "-", b, expr, config, refs),
+        Expr::Mul(a, b) => infix(a, "*", b, expr, config, refs),
+        Expr::Div(a, b) => infix(a, "/", b, expr, config, refs),
+        Expr::Pow(a, b) => infix(a, "^", b, expr, config, refs),
+        Expr::Concat(a, b) => infix(a, "&", b, expr, config, refs),
+        Expr::Cond(c, a, b) => infix(a, comp_str(c), b, expr, config, refs),
+        Expr::Func(f, args) => format!(
+            "{}({})",
+            f,
+            args.iter()
+                .map(|x| print_expr(x, config, refs))
+                .collect::<Vec<_>>()
+                .join(&config.arg_separator.to_string())
+        ),
+        Expr::Ref(r) => refs(r),
+        Expr::Error(e) => e.as_str().into(),
+        Expr::Array(rows) => format!(
+            "{{{}}}",
+            rows.iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|x| print_expr(x, config, refs))
+                        .collect::<Vec<_>>()
+                        .join(&config.array_column_separator.to_string())
+                })
+                .collect::<Vec<_>>()
+                .join(&config.array_row_separator.to_string())
+        ),
+        Expr::Name(name) => print_name(name, config),
+        Expr::Missing | Expr::Empty => String::new(),
+    }
+}
+
+/// Names that would be read back as something else, e.g. `A1` or `TRUE`, are
+/// printed in the explicit form `$$'A1'`.
+fn print_name(name: &str, config: &ParserConfig) -> String {
+    match parser_with_config(*config).parse(name).into_result() {
+        Ok(Expr::Name(n)) if n == name => n,
+        _ => format!("$$'{}'", name.replace('\'', "''")),
+    }
+}
+
+/// Returns the binding power of an expression as used by the parser.
+///
+/// Operands with a lower binding power than their operator need parentheses.
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Range(..) => 10,
+        Expr::RefIntersection(..) => 9,
+        Expr::RefUnion(..) => 8,
+        Expr::Neg(_) => 7,
+        // negative numbers are printed with a prefix minus
+        Expr::Num(n) if n.is_sign_negative() && n.is_finite() => 7,
+        Expr::Perc(_) => 6,
+        Expr::Pow(..) => 5,
+        Expr::Mul(..) | Expr::Div(..) => 4,
+        Expr::Add(..) | Expr::Sub(..) => 3,
+        Expr::Concat(..) => 2,
+        Expr::Cond(..) => 1,
+        _ => u8::MAX,
+    }
+}
+
+/// Returns true if an expression is printed as a range or an operation on ranges.
+fn is_area(expr: &Expr) -> bool {
+    match expr {
+        Expr::Range(..) => true,
+        Expr::Ref(r) => !is_cell(r),
+        _ => precedence(expr) != u8::MAX,
+    }
+}
+
+/// Returns true if a reference points to a single cell, possibly on another sheet.
+fn is_cell(r: &Ref) -> bool {
+    match r {
+        Ref::SheetRef(_, r) | Ref::External(_, r) => is_cell(r),
+        r => r.is_cell_ref(),
+    }
+}
+
+fn operand(expr: &Expr, min_precedence: u8, config: &ParserConfig, refs: &RefPrinter) -> String {
+    if precedence(expr) < min_precedence {
+        format!("({})", print_expr(expr, config, refs))
+    } else {
+        print_expr(expr, config, refs)
+    }
+}
+
+/// Prints a left associative infix operation.
+fn infix(
+    lhs: &Expr,
+    op: &str,
+    rhs: &Expr,
+    expr: &Expr,
+    config: &ParserConfig,
+    refs: &RefPrinter,
+) -> String {
+    let p = precedence(expr);
+    format!(
+        "{}{}{}",
+        operand(lhs, p, config, refs),
+        op,
+        operand(rhs, p + 1, config, refs)
+    )
+}
+
+fn comp_str(c: &Comp) -> &'static str {
+    match c {
+        Comp::Equal => "=",
+        Comp::NotEqual => "<>",
+        Comp::Greater => ">",
+        Comp::Lower => "<",
+        Comp::GreaterEqual => ">=",
+        Comp::LowerEqual => "<=",
+    }
+}
+
+/// Prints the shortest form of a number that parses into the same value,
+/// very large and small numbers with an exponent, e.g. `1E300`.
+///
+/// Infinity and NaN can't be written as numbers and are printed as `#NUM!`.
+fn print_num(n: f64, config: &ParserConfig) -> String {
+    if !n.is_finite() {
+        return "#NUM!".into();
+    }
+    let mut buffer = ryu::Buffer::new();
+    let s = buffer.format_finite(n);
+    let s = match s.split_once('e') {
+        Some((mantissa, exp)) => format!("{}E{}", mantissa.trim_end_matches(".0"), exp),
+        None => s.trim_end_matches(".0").to_string(),
+    };
+    if config.decimal_separator == '.' {
+        s
+    } else {
+        s.replace('.', &config.decimal_separator.to_string())
+    }
+}
+
+fn print_column(c: &Coord, style: RefStyle) -> String {
+    match style {
+        RefStyle::A1 => format!("{}{}", if c.abs { "$" } else { "" }, id_to_column(c.id)),
+        RefStyle::R1C1 { origin } => format!("C{}", print_r1c1_coord(c, origin.0)),
+    }
+}
+
+fn print_row(c: &Coord, style: RefStyle) -> String {
+    match style {
+        // ids are printed as 1-based numbers, widened so `usize::MAX` can't overflow
+        RefStyle::A1 => format!("{}{}", if c.abs { "$" } else { "" }, c.id as u128 + 1),
+        RefStyle::R1C1 { origin } => format!("R{}", print_r1c1_coord(c, origin.1)),
+    }
+}
+
+/// Prints the number after `R` or `C` in R1C1 notation.
+fn print_r1c1_coord(c: &Coord, origin: usize) -> String {
+    if c.abs {
+        format!("{}", c.id as u128 + 1)
+    } else if c.id == origin {
+        String::new()
+    } else if c.id > origin {
+        format!("[{}]", c.id - origin)
+    } else {
+        format!("[-{}]", origin - c.id)
+    }
+}
+
+fn print_cell(col: &Coord, row: &Coord, style: RefStyle) -> String {
+    match style {
+        RefStyle::A1 => format!("{}{}", print_column(col, style), print_row(row, style)),
+        RefStyle::R1C1 { .. } => format!("{}{}", print_row(row, style), print_column(col, style)),
+    }
+}
+
+/// Returns the start and end of a reference, if it spans a range.
+fn print_bounds(r: &Ref, style: RefStyle) -> Option<(String, String)> {
+    match r {
+        Ref::CellRef(col, row) => Some((print_cell(col, row, style), print_cell(col, row, style))),
+        Ref::ColumnRange(a, b) => Some((print_column(a, style), print_column(b, style))),
+        Ref::RowRange(a, b) => Some((print_row(a, style), print_row(b, style))),
+        Ref::CellRange((c1, r1), (c2, r2)) => {
+            Some((print_cell(c1, r1, style), print_cell(c2, r2, style)))
+        }
+        Ref::SheetRef(..) | Ref::SheetRange(..) | Ref::External(..) => None,
+    }
+}
+
+fn print_sheet_name(sheet: &SheetName, config: &ParserConfig) -> String {
+    let mut chars = sheet.name.chars();
+    let plain = chars.next().is_some_and(|c| c.is_xml_letter() || c == '_')
+        && chars.all(|c| c.is_xml_letter() || c.is_xml_digit() || c == '_');
+    let name = if plain {
+        sheet.name.clone()
+    } else {
+        format!("'{}'", sheet.name.replace('\'', "''"))
+    };
+    // Excel has no absolute sheet references
+    if sheet.abs && config.sheet_separator != '!' {
+        format!("${}", name)
+    } else {
+        name
+    }
+}
+
+/// Prints the document of an external reference, e.g. `'file:///data/rates.ods'#`.
+fn print_source(iri: &str) -> String {
+    format!("'{}'#", iri.replace('\'', "''"))
+}
+
+fn print_ref(r: &Ref, config: &ParserConfig) -> String {
+    match r {
+        Ref::External(iri, r) => format!("{}{}", print_source(iri), print_ref(r, config)),
+        Ref::SheetRef(sheet, r) => format!(
+            "{}{}{}",
+            print_sheet_name(sheet, config),
+            config.sheet_separator,
+            print_ref(r, config)
+        ),
+        Ref::SheetRange(a, b, r) => match print_bounds(r, config.ref_style) {
+            // e.g. Sheet1.A1:Sheet3.B2
+            Some((start, end)) if config.sheet_separator != '!' => format!(
+                "{}{}{}:{}{}{}",
+                print_sheet_name(a, config),
+                config.sheet_separator,
+                start,
+                print_sheet_name(b, config),
+                config.sheet_separator,
+                end
+            ),
+            // e.g. Sheet1:Sheet3!A1:B2
+            _ => format!(
+                "{}:{}{}{}",
+                print_sheet_name(a, config),
+                print_sheet_name(b, config),
+                config.sheet_separator,
+                print_ref(r, config)
+            ),
+        },
+        _ => match print_bounds(r, config.ref_style) {
+            Some((start, _)) if r.is_cell_ref() => start,
+            Some((start, end)) => format!("{}:{}", start, end),
+            None => unreachable!(),
+        },
+    }
+}
+
+fn print_odf_sheet_name(sheet: &SheetName) -> String {
+    let plain = !sheet.name.is_empty() && !sheet.name.contains(['.', ']', ' ', '#', '$', '\'']);
+    format!(
+        "{}{}",
+        if sheet.abs { "$" } else { "" },
+        if plain {
+            sheet.name.clone()
+        } else {
+            format!("'{}'", sheet.name.replace('\'', "''"))
+        }
+    )
+}
+
+/// Prints a reference in brackets, e.g. `[.A1]` or `[$Sheet1.A1:.B2]`.
+fn print_odf_ref(r: &Ref) -> String {
+    let (start, end, r) = match r {
+        // the document is written inside the brackets, e.g. ['a.ods'#$Sheet1.A1]
+        Ref::External(iri, r) => {
+            return format!("[{}{}", print_source(iri), &print_odf_ref(r)[1..])
+        }
+        Ref::SheetRef(sheet, r) => (Some(sheet), None, r.as_ref()),
+        Ref::SheetRange(a, b, r) => (Some(a), Some(b), r.as_ref()),
+        r => (None, None, r),
+    };
+    let sheet = |s: Option<&SheetName>| s.map(print_odf_sheet_name).unwrap_or_default();
+    match print_bounds(r, RefStyle::A1) {
+        Some((a, _)) if r.is_cell_ref() => format!("[{}.{}]", sheet(start), a),
+        Some((a, b)) => format!("[{}.{}:{}.{}]", sheet(start), a, sheet(end), b),
+        None => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parser, parser_with_config, Parser};
+    use crate::types::Ref;
+
+    fn roundtrip(input: &str) -> String {
+        print(&parser().parse(input).unwrap())
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(roundtrip("3"), "3");
+        assert_eq!(roundtrip("3.25"), "3.25");
+        assert_eq!(roundtrip("1E3"), "1000");
+        assert_eq!(roundtrip("TRUE"), "TRUE");
+        assert_eq!(roundtrip("\"say \"\"hi\"\"\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(roundtrip("#N/A"), "#N/A");
+        assert_eq!(roundtrip("{1;-2|\"A\";FALSE}"), "{1;-2|\"A\";FALSE}");
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(roundtrip("A1"), "A1");
+        assert_eq!(roundtrip("$A$1:B$2"), "$A$1:B$2");
+        assert_eq!(roundtrip("[.A:.$C]"), "A:$C");
+        assert_eq!(roundtrip("3:$5"), "3:$5");
+        assert_eq!(roundtrip("[$Sheet1.A1]"), "$Sheet1.A1");
+        assert_eq!(roundtrip("'My ''Sheet'''.A1:B2"), "'My ''Sheet'''.A1:B2");
+        assert_eq!(roundtrip("Sheet1.A1:Sheet3.B2"), "Sheet1.A1:Sheet3.B2");
+        assert_eq!(
+            roundtrip("['file:///it''s.ods'#$Sheet1.A1:.B2]"),
+            "'file:///it''s.ods'#$Sheet1.A1:B2"
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(roundtrip("1 + 2 * 3"), "1+2*3");
+        assert_eq!(roundtrip("(1 + 2) * 3"), "(1+2)*3");
+        assert_eq!(roundtrip("1 - (2 - 3)"), "1-(2-3)");
+        assert_eq!(roundtrip("(1 - 2) - 3"), "1-2-3");
+        assert_eq!(roundtrip("-2^2"), "-2^2");
+        assert_eq!(roundtrip("-(2^2)"), "-(2^2)");
+        assert_eq!(roundtrip("-(2%)"), "-(2%)");
+        assert_eq!(roundtrip("2*-20%%"), "2*-20%%");
+        assert_eq!(roundtrip("\"A\" & (1 = 2)"), "\"A\"&(1=2)");
+        assert_eq!(roundtrip("SUM(A1:B2 ; 3)"), "SUM(A1:B2;3)");
+        assert_eq!(roundtrip("1E300+1.5E-7+2.50+1E3"), "1E300+1.5E-7+2.5+1000");
+        assert_eq!(roundtrip("12345678901234567890"), "1.2345678901234567E19");
+        assert_eq!(print(&Expr::Num(f64::INFINITY)), "#NUM!");
+        assert_eq!(print(&Expr::Neg(Box::new(Expr::Num(f64::NAN)))), "-#NUM!");
+    }
+
+    #[test]
+    fn config() {
+        let config = ParserConfig::excel();
+        let expr = parser_with_config(config)
+            .parse("SUM(Sheet1!A1:B2,{1.5,2;3,4},Sheet1:Sheet3!C1)")
+            .unwrap();
+        assert_eq!(
+            print_with_config(&expr, &config),
+            "SUM(Sheet1!A1:B2,{1.5,2;3,4},Sheet1:Sheet3!C1)"
+        );
+        let config = ParserConfig {
+            decimal_separator: ',',
+            ..Default::default()
+        };
+        assert_eq!(print_with_config(&Expr::Num(1.5), &config), "1,5");
+    }
+
+    #[test]
+    fn reparse() {
+        for input in [
+            "-(-1)",
+            "--1",
+            "1-(-1)",
+            "2^3^4",
+            "2^(3^4)",
+            "(1+2)%",
+            "-A1%",
+            "1<(2<3)",
+            "(\"a\"&\"b\")&(1>2)",
+            "A1:B2!B1:C3~D4",
+            "A1:(B2!C3)",
+            "(A1~B2)!C3",
+            "SUM(IF(A1>=0;{1;2|3;4};\"\"\"\");Sheet1.A1:Sheet2.B2;-[.1:.2])",
+            "'Sheet 1'.$A:.B*$Sheet2.3:4",
+            "1E300*1E-300+0.1",
+            "SUM(1E300;-1.7976931348623157E308;12345678901234567890;5E-324;0.000001)",
+            "1.5E-7+2.5E+16+123456.789+0",
+            "[.#REF!]+#DIV/0!",
+            "IF(A1;;0)+SUM(1;)",
+            "TaxRate*SUM(Sales)+$$'A1'&$$'Tax Rate'&$$'TRUE'",
+            "'file:///data/rates.ods'#$Sheet1.A1*'rates.ods'#B1:C2",
+            "('rates.ods'#A1):B1",
+        ] {
+            let expr = parser().parse(input).unwrap();
+            for formula in [print(&expr), print_odf(&expr)] {
+                assert_eq!(parser().parse(&formula).unwrap(), expr, "{}", formula);
+            }
+        }
+    }
+
+    #[test]
+    fn odf() {
+        let odf = |input| print_odf(&parser().parse(input).unwrap());
+        assert_eq!(odf("A1+1"), "of:=[.A1]+1");
+        assert_eq!(odf("[.A:.$C]~[.$1:.2]"), "of:=[.A:.$C]~[.$1:.2]");
+        assert_eq!(odf("$Sheet1.A1:Sheet3.B2"), "of:=[$Sheet1.A1:Sheet3.B2]");
+        assert_eq!(odf("'a''b'.A1"), "of:=['a''b'.A1]");
+    }
+
+    #[test]
+    fn ranges() {
+        let range = |a, b| Expr::Range(Box::new(a), Box::new(b));
+        let expr = range(Expr::Ref(Ref::cell(0, 0)), Expr::Ref(Ref::cell(1, 1)));
+        assert_eq!(print(&expr), "A1:(B2)");
+        let expr = range(
+            Expr::Ref(Ref::cell_range((0, 0), (1, 1))),
+            Expr::Ref(Ref::cell(2, 2)),
+        );
+        assert_eq!(print(&expr), "A1:B2:C3");
+        assert_eq!(roundtrip("A1:B2:C3"), "A1:B2:C3");
+        assert_eq!(roundtrip("(A1):B2"), "A1:(B2)");
+        assert_eq!(roundtrip("(1):2"), "1:(2)");
+    }
+
+    #[test]
+    fn r1c1() {
+        let config = ParserConfig {
+            ref_style: RefStyle::R1C1 { origin: (2, 4) },
+            ..ParserConfig::excel()
+        };
+        let expr = parser().parse("A1+$A$1*SUM(C4:D$5;$B:C;5:$7)").unwrap();
+        assert_eq!(
+            print_with_config(&expr, &config),
+            "R[-4]C[-2]+R1C1*SUM(R[-1]C:R5C[1],C2:C,R:R7)"
+        );
+        let formula = print_with_config(&expr, &config);
+        assert_eq!(parser_with_config(config).parse(&formula).unwrap(), expr);
+    }
+}
@@ -44,10 +44,10 @@ pub fn transform(expr: &Expr) -> Result<String, Error> {
 /// ```rust
 /// use open_formula::{sql::transform_with_columns, types::{Expr, Ref}};
 /// let cols = ["foo", "bar", "baz"].map(String::from).to_vec();
-/// assert_eq!(transform_with_columns(&Expr::Ref(Ref::CellRef(1, 0)), &cols).unwrap(), "bar");
+/// assert_eq!(transform_with_columns(&Expr::Ref(Ref::cell(1, 0)), &cols).unwrap(), "bar");
 /// assert_eq!(transform_with_columns(&Expr::Add(
-///     Box::new(Expr::Ref(Ref::CellRef(0, 0))),
-///     Box::new(Expr::Ref(Ref::CellRef(2, 0)))
+///     Box::new(Expr::Ref(Ref::cell(0, 0))),
+///     Box::new(Expr::Ref(Ref::cell(2, 0)))
 /// ), &cols).unwrap(), "foo + baz");
 /// ```
 pub fn transform_with_columns(expr: &Expr, columns: &Vec<String>) -> Result<String, Error> {
@@ -90,19 +90,19 @@ fn transform_(expr: &Expr, ctx: &mut Context) -> Result<String, Error> {
         )),
         Expr::Ref(Ref::CellRef(col, row)) => {
             // check if index is out of bounds
-            if *col >= ctx.columns.len() {
+            if col.id >= ctx.columns.len() {
                 return Err(Error::ColumnIndexOutOfBounds);
             }
             // check if all references are on the same row
             if let Some(n) = ctx.row {
-                if n != *row {
+                if n != row.id {
                     return Err(Error::MultipleRowsReferenced);
                 }
             } else {
-                ctx.row = Some(*row);
+                ctx.row = Some(row.id);
             }
 
-            Ok(ctx.columns.get(*col).unwrap().clone())
+            Ok(ctx.columns.get(col.id).unwrap().clone())
         }
         Expr::Func(f, args) => {
             // TODO: map common formula functions to sql logic
@@ -141,7 +141,7 @@ mod tests {
         let cols = ["foo", "bar", "baz"].map(String::from).to_vec();
         let check_ref = |a, b| {
             assert_eq!(
-                transform_with_columns(&Expr::Ref(Ref::CellRef(a, 0)), &cols).unwrap(),
+                transform_with_columns(&Expr::Ref(Ref::cell(a, 0)), &cols).unwrap(),
                 b
             );
         };
@@ -155,7 +155,7 @@ mod tests {
         let cols = ["foo", "bar", "baz"].map(String::from).to_vec();
         let check_ref = |col: usize, row: usize, e: Error| {
             assert_eq!(
-                transform_with_columns(&Expr::Ref(Ref::CellRef(col, row)), &cols),
+                transform_with_columns(&Expr::Ref(Ref::cell(col, row)), &cols),
                 Err(e)
             );
         };
@@ -169,7 +169,7 @@ mod tests {
         let cols = ["foo", "bar", "baz"].map(String::from).to_vec();
         let check_refs = |rows: Vec<usize>, e: Result<String, Error>| {
             let expr = rows.iter().fold(Expr::Num(0.0), |sum, x| {
-                Expr::Add(Box::new(sum), Box::new(Expr::Ref(Ref::CellRef(0, *x))))
+                Expr::Add(Box::new(sum), Box::new(Expr::Ref(Ref::cell(0, *x))))
             });
             assert_eq!(transform_with_columns(&expr, &cols), e);
         };
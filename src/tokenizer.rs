@@ -0,0 +1,317 @@
+//! A tokenizer for formula strings, e.g. for syntax highlighting in editors.
+//!
+//! Unlike the [parser](crate::parser), the tokenizer accepts any input. Characters
+//! that don't start a valid token are returned as [`TokenKind::Unknown`].
+
+use std::ops::Range;
+
+use chumsky::prelude::*;
+
+use crate::{
+    parser::{sheet_area, sheet_qualified_ref, source, ParserConfig},
+    xmlchar::XmlChar,
+};
+
+/// The kind of a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// The formula prefix `of:=` or `=` at the start of the input.
+    Prefix,
+    Whitespace,
+    Number,
+    /// A string literal in quotes, possibly without the closing quote.
+    String,
+    /// The boolean constants `TRUE` and `FALSE`.
+    Bool,
+    /// An error literal, e.g. `#DIV/0!`.
+    Error,
+    /// A reference, e.g. `A1`, `Sheet2.B1:C3` or `[.A1]`.
+    Reference,
+    /// The name of a function that is called, e.g. `SUM` in `SUM(1;2)`.
+    Function,
+    /// Any other identifier, e.g. a named expression.
+    Name,
+    /// An operator, e.g. `+`, `<>` or `:`.
+    Operator,
+    /// A separator of function arguments or inline array elements.
+    Separator,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+    /// A character that does not start a valid token.
+    Unknown,
+}
+
+/// A token of a formula string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// Byte range of the token in the formula string.
+    pub span: Range<usize>,
+}
+
+/// Splits a formula string into tokens.
+///
+/// Example
+/// ```rust
+/// use open_formula::tokenizer::{tokenize, TokenKind};
+/// let kinds: Vec<_> = tokenize("=SUM(A1; 2").into_iter().map(|t| t.kind).collect();
+/// assert_eq!(kinds, vec![
+///     TokenKind::Prefix,
+///     TokenKind::Function,
+///     TokenKind::OpenParen,
+///     TokenKind::Reference,
+///     TokenKind::Separator,
+///     TokenKind::Whitespace,
+///     TokenKind::Number,
+/// ]);
+/// ```
+pub fn tokenize(input: &str) -> Vec<Token> {
+    tokenize_with_config(input, ParserConfig::default())
+}
+
+/// Splits a formula string into tokens using the syntax of the given configuration.
+pub fn tokenize_with_config(input: &str, config: ParserConfig) -> Vec<Token> {
+    tokenizer(config)
+        .parse(input)
+        .into_output()
+        .unwrap_or_default()
+}
+
+fn tokenizer<'a>(
+    config: ParserConfig,
+) -> impl Parser<'a, &'a str, Vec<Token>, extra::Err<Rich<'a, char>>> {
+    let ident_char = any().filter(|c: &char| {
+        c.is_xml_letter() || c.is_xml_digit() || *c == '_' || *c == '.' || c.is_xml_combining_char()
+    });
+    let ident = any()
+        .filter(|c: &char| c.is_xml_letter() || *c == '_')
+        .then(ident_char.repeated());
+    let digits = text::digits(10);
+    let decimal_separator = just(config.decimal_separator);
+    let number = choice((
+        digits
+            .then(decimal_separator.then(digits).or_not())
+            .ignored(),
+        decimal_separator.then(digits).ignored(),
+    ))
+    .then(
+        one_of("eE")
+            .then(one_of("+-").or_not())
+            .then(digits)
+            .or_not(),
+    );
+    // strings and brackets may be unterminated while typing
+    let string = just('"')
+        .then(none_of('"').ignored().or(just("\"\"").ignored()).repeated())
+        .then(just('"').or_not());
+    let quoted = just('\'')
+        .then(none_of('\'').repeated())
+        .then(just('\'').or_not());
+    let odf_ref = just('[')
+        .then(quoted.ignored().or(none_of("]'").ignored()).repeated())
+        .then(just(']').or_not());
+    let name = just("$$").then(quoted.ignored().or(ident.ignored()));
+    let no_sheet = empty().to(None);
+    let reference = choice((
+        odf_ref.ignored(),
+        source()
+            .then(choice((
+                sheet_qualified_ref(config),
+                sheet_area(config.ref_style, no_sheet.clone(), no_sheet.clone()),
+            )))
+            .ignored(),
+        sheet_qualified_ref(config).ignored(),
+        sheet_area(config.ref_style, no_sheet.clone(), no_sheet).ignored(),
+    ))
+    // identifiers that only start like a reference are names, e.g. `A1B`
+    .then_ignore(ident_char.not());
+    let error = choice((
+        just("#NULL!"),
+        just("#DIV/0!"),
+        just("#VALUE!"),
+        just("#REF!"),
+        just("#NAME?"),
+        just("#NUM!"),
+        just("#N/A"),
+    ));
+    let operator = choice((
+        just("<>"),
+        just(">="),
+        just("<="),
+        one_of("+-*/^&%:!~=<>").to_slice(),
+    ));
+    let separator = one_of([
+        config.arg_separator,
+        config.array_column_separator,
+        config.array_row_separator,
+    ]);
+
+    let token = choice((
+        text::whitespace().at_least(1).to(TokenKind::Whitespace),
+        string.to(TokenKind::String),
+        error.to(TokenKind::Error),
+        // whitespace before the parenthesis is a token of its own
+        ident
+            .then(text::whitespace().then(just('(')).rewind())
+            .to(TokenKind::Function),
+        reference.to(TokenKind::Reference),
+        name.to(TokenKind::Name),
+        number.to(TokenKind::Number),
+        choice((text::keyword("TRUE"), text::keyword("FALSE"))).to(TokenKind::Bool),
+        ident.to(TokenKind::Name),
+        separator.to(TokenKind::Separator),
+        operator.to(TokenKind::Operator),
+        just('(').to(TokenKind::OpenParen),
+        just(')').to(TokenKind::CloseParen),
+        just('{').to(TokenKind::OpenBrace),
+        just('}').to(TokenKind::CloseBrace),
+        any().to(TokenKind::Unknown),
+    ))
+    .map_with(|kind, e| Token {
+        kind,
+        span: e.span().into_range(),
+    });
+    let prefix = choice((just("of:="), just("=")))
+        .map_with(|_, e| Token {
+            kind: TokenKind::Prefix,
+            span: SimpleSpan::into_range(e.span()),
+        })
+        .or_not();
+
+    prefix
+        .then(token.repeated().collect::<Vec<_>>())
+        .map(|(prefix, tokens)| prefix.into_iter().chain(tokens).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(input: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(input)
+            .into_iter()
+            .map(|t| (t.kind, &input[t.span]))
+            .collect()
+    }
+
+    #[test]
+    fn formulas() {
+        use TokenKind::*;
+        assert_eq!(
+            tokens("of:=IF([.A1]>=1E3;\"a\"\"b\";#N/A)"),
+            vec![
+                (Prefix, "of:="),
+                (Function, "IF"),
+                (OpenParen, "("),
+                (Reference, "[.A1]"),
+                (Operator, ">="),
+                (Number, "1E3"),
+                (Separator, ";"),
+                (String, "\"a\"\"b\""),
+                (Separator, ";"),
+                (Error, "#N/A"),
+                (CloseParen, ")"),
+            ]
+        );
+        assert_eq!(
+            tokens("-'My Sheet'.A1:B2*Sheet1.A1:Sheet3.B2&TaxRate"),
+            vec![
+                (Operator, "-"),
+                (Reference, "'My Sheet'.A1:B2"),
+                (Operator, "*"),
+                (Reference, "Sheet1.A1:Sheet3.B2"),
+                (Operator, "&"),
+                (Name, "TaxRate"),
+            ]
+        );
+        assert_eq!(
+            tokens("'file:///a.ods'#$Sheet1.A1+['a.ods'#.B2]"),
+            vec![
+                (Reference, "'file:///a.ods'#$Sheet1.A1"),
+                (Operator, "+"),
+                (Reference, "['a.ods'#.B2]"),
+            ]
+        );
+        assert_eq!(
+            tokens("$$'Tax Rate'*A1B"),
+            vec![(Name, "$$'Tax Rate'"), (Operator, "*"), (Name, "A1B"),]
+        );
+        assert_eq!(
+            tokens("{1;TRUE|.5;FALSE} ~ 1:3~A:B"),
+            vec![
+                (OpenBrace, "{"),
+                (Number, "1"),
+                (Separator, ";"),
+                (Bool, "TRUE"),
+                (Separator, "|"),
+                (Number, ".5"),
+                (Separator, ";"),
+                (Bool, "FALSE"),
+                (CloseBrace, "}"),
+                (Whitespace, " "),
+                (Operator, "~"),
+                (Whitespace, " "),
+                (Reference, "1:3"),
+                (Operator, "~"),
+                (Reference, "A:B"),
+            ]
+        );
+    }
+
+    #[test]
+    fn function_whitespace() {
+        use TokenKind::*;
+        assert_eq!(
+            tokens("SUM (1)"),
+            vec![
+                (Function, "SUM"),
+                (Whitespace, " "),
+                (OpenParen, "("),
+                (Number, "1"),
+                (CloseParen, ")"),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_input() {
+        use TokenKind::*;
+        assert_eq!(
+            tokens("SUM(A1;\"ab"),
+            vec![
+                (Function, "SUM"),
+                (OpenParen, "("),
+                (Reference, "A1"),
+                (Separator, ";"),
+                (String, "\"ab"),
+            ]
+        );
+        assert_eq!(
+            tokens("1 ? [.A"),
+            vec![
+                (Number, "1"),
+                (Whitespace, " "),
+                (Unknown, "?"),
+                (Whitespace, " "),
+                (Reference, "[.A"),
+            ]
+        );
+        assert_eq!(tokens(""), vec![]);
+    }
+
+    #[test]
+    fn config() {
+        use TokenKind::*;
+        let config = ParserConfig::excel();
+        let tokens: Vec<_> = tokenize_with_config("SUM(Sheet1!A1,1.5)", config)
+            .into_iter()
+            .map(|t| t.kind)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![Function, OpenParen, Reference, Separator, Number, CloseParen]
+        );
+    }
+}
@@ -4,7 +4,7 @@ use enum_as_inner::EnumAsInner;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 /// OpenFormula [Error](https://docs.oasis-open.org/office/OpenDocument/v1.4/csd01/part4-formula/OpenDocument-v1.4-csd01-part4-formula.html#__RefHeading__1017900_715980110) type.
-#[derive(Debug, Clone, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
 #[repr(u8)]
 pub enum Error {
     // OpenDocument / Google Sheets / Excel
@@ -22,6 +22,30 @@ pub enum Error {
     Args = 10,
 }
 
+impl Error {
+    /// Returns the error constant as written in a formula, e.g. `#DIV/0!`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Null => "#NULL!",
+            Self::Div0 => "#DIV/0!",
+            Self::Value => "#VALUE!",
+            Self::Ref => "#REF!",
+            Self::Name => "#NAME?",
+            Self::Num => "#NUM!",
+            Self::NotAvailable => "#N/A",
+            Self::GettingData => "#GETTING_DATA",
+            Self::Unimplemented => "#UNIMPLEMENTED!",
+            Self::Args => "#ARGS!",
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// A result type that uses an OpenFormula [Error] type.
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -35,6 +59,9 @@ pub enum Value {
     Bool(bool),
     EmptyCell,
     Ref(Ref),
+    Error(Error),
+    /// A two dimensional array, stored as a list of rows.
+    Array(Vec<Vec<Value>>),
 }
 
 /// A comparison operator.
@@ -69,26 +96,378 @@ pub enum Expr {
     Concat(Box<Self>, Box<Self>),
     Cond(Comp, Box<Self>, Box<Self>),
     Func(String, Vec<Self>),
+    /// A named expression, e.g. `TaxRate` in `TaxRate*A1`.
+    ///
+    /// Names that look like a reference are parsed as the reference, e.g.
+    /// `Sales.Q1` is the cell `Q1` of the sheet `Sales`. Such names are written
+    /// in the explicit form `$$'Sales.Q1'`, which the printer uses for them.
+    Name(String),
     Ref(Ref),
+    Error(Error),
+    /// An inline array, e.g. `{1;2;3|4;5;6}`, stored as a list of rows.
+    Array(Vec<Vec<Self>>),
+    /// A placeholder for a part of an incomplete formula, e.g. the second
+    /// argument of `SUM(A1;`, produced by error recovery of the parser.
+    Missing,
+    /// An omitted function parameter, e.g. the second parameter of `IF(A1;;0)`.
+    ///
+    /// Functions can tell it apart from a zero or an empty string to apply
+    /// their defaults. Evaluated on its own it yields [`Value::EmptyCell`].
+    Empty,
+}
+
+/// A single column or row coordinate of a reference.
+///
+/// Coordinates are zero-based ids. An absolute coordinate (written with a `$`
+/// marker, e.g. `$A` or `$1`) keeps pointing to the same column or row when a
+/// formula is copied, a relative one moves along.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Coord {
+    pub id: usize,
+    pub abs: bool,
+}
+
+impl Coord {
+    /// The largest id of a row or column, so that its 1-based number can
+    /// still be printed.
+    pub const MAX_ID: usize = usize::MAX - 1;
+
+    /// Returns a relative coordinate.
+    pub const fn relative(id: usize) -> Self {
+        Self { id, abs: false }
+    }
+
+    /// Returns an absolute coordinate.
+    pub const fn absolute(id: usize) -> Self {
+        Self { id, abs: true }
+    }
+
+    /// Moves a relative coordinate by the given offset, absolute coordinates
+    /// stay unchanged.
+    ///
+    /// Returns `None` if the coordinate is moved off the sheet.
+    pub fn offset(self, delta: isize) -> Option<Self> {
+        if self.abs {
+            Some(self)
+        } else {
+            self.id
+                .checked_add_signed(delta)
+                .filter(|id| *id <= Self::MAX_ID)
+                .map(Self::relative)
+        }
+    }
+}
+
+impl From<usize> for Coord {
+    fn from(id: usize) -> Self {
+        Self::relative(id)
+    }
+}
+
+/// The name of a sheet used in a reference.
+///
+/// An absolute sheet name is written with a `$` marker, e.g. `$Sheet1`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SheetName {
+    pub name: String,
+    pub abs: bool,
+}
+
+impl SheetName {
+    /// Returns a relative sheet name.
+    pub fn relative<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            abs: false,
+        }
+    }
+
+    /// Returns an absolute sheet name.
+    pub fn absolute<S: Into<String>>(name: S) -> Self {
+        Self {
+            name: name.into(),
+            abs: true,
+        }
+    }
 }
 
 /// An OpenFomula [reference](https://docs.oasis-open.org/office/OpenDocument/v1.4/csd01/part4-formula/OpenDocument-v1.4-csd01-part4-formula.html#__RefHeading__74715_1363921367).
+///
+/// Cells are addressed by (column, row) coordinates. References without a
+/// sheet name point into the sheet the formula is evaluated in.
 #[derive(Debug, Clone, EnumAsInner, PartialEq)]
 pub enum Ref {
-    CellRef(usize, usize),
-    ColumnRange(usize, usize),
-    RowRange(usize, usize),
-    CellRange((usize, usize), (usize, usize)),
+    CellRef(Coord, Coord),
+    ColumnRange(Coord, Coord),
+    RowRange(Coord, Coord),
+    CellRange((Coord, Coord), (Coord, Coord)),
+    /// A reference into a single named sheet, e.g. `Sheet2.A1`.
+    SheetRef(SheetName, Box<Ref>),
+    /// A reference spanning all sheets from the first to the second one,
+    /// e.g. `Sheet1.A1:Sheet3.B2`.
+    SheetRange(SheetName, SheetName, Box<Ref>),
+    /// A reference into another document, identified by its IRI, e.g.
+    /// `'file:///data/rates.ods'#$Sheet1.A1`.
+    External(String, Box<Ref>),
 }
 
-impl Expr {
-    /// Returns all references that are used in an expression.
-    pub fn refs(&self) -> Box<dyn Iterator<Item = Ref>> {
+impl Ref {
+    /// Returns a relative reference to a single cell.
+    pub fn cell(col: usize, row: usize) -> Self {
+        Self::CellRef(col.into(), row.into())
+    }
+
+    /// Returns a relative reference to all cells between two columns, in
+    /// either order.
+    pub fn column_range(a: usize, b: usize) -> Self {
+        Self::ColumnRange(a.into(), b.into()).normalize()
+    }
+
+    /// Returns a relative reference to all cells between two rows, in either
+    /// order.
+    pub fn row_range(a: usize, b: usize) -> Self {
+        Self::RowRange(a.into(), b.into()).normalize()
+    }
+
+    /// Returns a relative reference to all cells between two opposite corners.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::types::*;
+    /// assert_eq!(Ref::cell_range((1, 4), (0, 0)), Ref::cell_range((0, 0), (1, 4)));
+    /// ```
+    pub fn cell_range(a: (usize, usize), b: (usize, usize)) -> Self {
+        Self::CellRange((a.0.into(), a.1.into()), (b.0.into(), b.1.into())).normalize()
+    }
+
+    /// Orders the corners of a range, so that the start is the upper left and
+    /// the end the lower right corner, e.g. `B5:A1` becomes `A1:B5`.
+    ///
+    /// Columns and rows are ordered independently, each coordinate keeps its
+    /// absolute marker.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::types::*;
+    /// let r = Ref::CellRange(
+    ///     (Coord::absolute(0), Coord::relative(4)),
+    ///     (Coord::relative(1), Coord::absolute(0)),
+    /// );
+    /// assert_eq!(
+    ///     r.normalize(),
+    ///     Ref::CellRange(
+    ///         (Coord::absolute(0), Coord::absolute(0)),
+    ///         (Coord::relative(1), Coord::relative(4)),
+    ///     )
+    /// );
+    /// ```
+    pub fn normalize(self) -> Self {
+        fn order(a: Coord, b: Coord) -> (Coord, Coord) {
+            if a.id <= b.id {
+                (a, b)
+            } else {
+                (b, a)
+            }
+        }
+
         match self {
-            Expr::Perc(a) | Expr::Neg(a) => a.refs(),
-            Expr::Add(a, b) => Box::new(a.refs().chain(b.refs())),
-            Expr::Ref(r) => Box::new(std::iter::once(r.clone())),
-            _ => Box::new(std::iter::empty()),
+            Self::CellRef(..) => self,
+            Self::ColumnRange(a, b) => {
+                let (a, b) = order(a, b);
+                Self::ColumnRange(a, b)
+            }
+            Self::RowRange(a, b) => {
+                let (a, b) = order(a, b);
+                Self::RowRange(a, b)
+            }
+            Self::CellRange((c1, r1), (c2, r2)) => {
+                let (c1, c2) = order(c1, c2);
+                let (r1, r2) = order(r1, r2);
+                Self::CellRange((c1, r1), (c2, r2))
+            }
+            Self::SheetRef(sheet, r) => Self::SheetRef(sheet, Box::new(r.normalize())),
+            Self::SheetRange(a, b, r) => Self::SheetRange(a, b, Box::new(r.normalize())),
+            Self::External(iri, r) => Self::External(iri, Box::new(r.normalize())),
         }
     }
+
+    /// Returns this reference qualified with the given sheet name.
+    pub fn on_sheet(self, sheet: SheetName) -> Self {
+        Self::SheetRef(sheet, Box::new(self))
+    }
+
+    /// Moves all relative coordinates of a reference by `dx` columns and `dy`
+    /// rows, e.g. when a formula is copied to another cell.
+    ///
+    /// Returns `None` if any part of the reference is moved off the sheet.
+    /// Ranges whose corners change places, e.g. `$C1:D1` moved two columns to
+    /// the left, are [normalized](Ref::normalize).
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::types::*;
+    /// let r = Ref::CellRef(Coord::absolute(0), Coord::relative(0));
+    /// assert_eq!(
+    ///     r.relocate(2, 1),
+    ///     Some(Ref::CellRef(Coord::absolute(0), Coord::relative(1)))
+    /// );
+    /// assert_eq!(Ref::cell(1, 1).relocate(-2, 0), None);
+    /// ```
+    pub fn relocate(&self, dx: isize, dy: isize) -> Option<Self> {
+        Some(match self {
+            Self::CellRef(col, row) => Self::CellRef(col.offset(dx)?, row.offset(dy)?),
+            Self::ColumnRange(a, b) => Self::ColumnRange(a.offset(dx)?, b.offset(dx)?).normalize(),
+            Self::RowRange(a, b) => Self::RowRange(a.offset(dy)?, b.offset(dy)?).normalize(),
+            Self::CellRange((c1, r1), (c2, r2)) => Self::CellRange(
+                (c1.offset(dx)?, r1.offset(dy)?),
+                (c2.offset(dx)?, r2.offset(dy)?),
+            )
+            .normalize(),
+            Self::SheetRef(sheet, r) => {
+                Self::SheetRef(sheet.clone(), Box::new(r.relocate(dx, dy)?))
+            }
+            Self::SheetRange(a, b, r) => {
+                Self::SheetRange(a.clone(), b.clone(), Box::new(r.relocate(dx, dy)?))
+            }
+            Self::External(iri, r) => Self::External(iri.clone(), Box::new(r.relocate(dx, dy)?)),
+        })
+    }
+}
+
+/// Source positions of an expression and its operands.
+///
+/// The children are ordered like the operands of the expression: left before
+/// right, function arguments in order and array elements row by row.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Spans {
+    /// Byte range of the expression in the formula string.
+    pub span: std::ops::Range<usize>,
+    pub children: Vec<Spans>,
+}
+
+impl Expr {
+    /// Returns all references that are used in an expression, in the order
+    /// they appear in the formula.
+    pub fn refs(&self) -> Box<dyn Iterator<Item = Ref>> {
+        let refs = self.fold(vec![], |mut refs, e| {
+            if let Expr::Ref(r) = e {
+                refs.push(r.clone());
+            }
+            refs
+        });
+        Box::new(refs.into_iter())
+    }
+
+    /// Returns a copy of an expression with all relative references moved by
+    /// `dx` columns and `dy` rows, e.g. when a formula is copied from one
+    /// cell to another.
+    ///
+    /// References that are moved off the sheet are replaced by `#REF!`.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::{parser::{Parser, parser}, types::Expr};
+    /// let expr = parser().parse("A1+B1*$A$1").unwrap();
+    /// assert_eq!(expr.relocate(0, 1).to_string(), "A2+B2*$A$1");
+    /// assert_eq!(expr.relocate(-1, 0).to_string(), "#REF!+A1*$A$1");
+    /// ```
+    pub fn relocate(&self, dx: isize, dy: isize) -> Self {
+        self.clone().map(&mut |expr| match expr {
+            Expr::Ref(r) => r
+                .relocate(dx, dy)
+                .map_or(Expr::Error(Error::Ref), Expr::Ref),
+            expr => expr,
+        })
+    }
+
+    /// Returns the formula string of an expression as stored in OpenDocument
+    /// files, e.g. `of:=[.A1]+1`.
+    ///
+    /// Parsing the formula string yields the same expression again.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::{parser::{Parser, parser}, types::Expr};
+    /// let expr = parser().parse("(1+2)*A1").unwrap();
+    /// assert_eq!(expr.to_formula_string(), "of:=(1+2)*[.A1]");
+    /// assert_eq!(expr.to_string(), "(1+2)*A1");
+    /// let formula = expr.to_formula_string();
+    /// assert_eq!(parser().parse(&formula).unwrap(), expr);
+    /// ```
+    pub fn to_formula_string(&self) -> String {
+        crate::printer::print_odf(self)
+    }
+}
+
+/// Formats an expression as a minimally parenthesized formula, without prefix.
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", crate::printer::print(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parser, Parser};
+
+    fn parse(input: &str) -> Expr {
+        parser().parse(input).unwrap()
+    }
+
+    #[test]
+    fn relocate() {
+        let relocate = |input: &str, dx, dy| parse(input).relocate(dx, dy).to_string();
+        assert_eq!(relocate("A1+B1", 0, 1), "A2+B2");
+        assert_eq!(relocate("$A$1+A$1+$A1", 1, 1), "$A$1+B$1+$A2");
+        assert_eq!(relocate("SUM(B2:C3;D:E;4:5)", -1, -1), "SUM(A1:B2;C:D;3:4)");
+        assert_eq!(relocate("Sheet2.B2:Sheet3.C3", 1, 0), "Sheet2.C2:Sheet3.D3");
+        assert_eq!(relocate("'a.ods'#$Sheet1.B2", 0, 2), "'a.ods'#$Sheet1.B4");
+        assert_eq!(relocate("-IF(A1;{1};B2)", 0, 0), "-IF(A1;{1};B2)");
+        // references moved off the sheet
+        assert_eq!(relocate("A1+$A$1", 0, -1), "#REF!+$A$1");
+        assert_eq!(relocate("SUM(A1:C3)", -1, 0), "SUM(#REF!)");
+        assert_eq!(relocate("B:C", -2, 0), "#REF!");
+        assert_eq!(relocate("Sheet2.A1", -1, 0), "#REF!");
+        let far = parse("A1").relocate(0, isize::MAX).relocate(0, isize::MAX);
+        assert_eq!(far.relocate(0, 1).to_string(), "#REF!");
+        // hand-built references beyond the sheet still print
+        let r = Ref::CellRef(Coord::relative(usize::MAX), Coord::absolute(usize::MAX));
+        assert_eq!(
+            Expr::Ref(r).to_string(),
+            "GKGWBYLWRXTLPP$18446744073709551616"
+        );
+        // corners that change places
+        assert_eq!(relocate("$C1:D1", -2, 0), "B1:$C1");
+        assert_eq!(relocate("A$5:A1", 0, 6), "A$5:A7");
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(Ref::row_range(20, 8), Ref::row_range(8, 20));
+        assert_eq!(
+            Ref::column_range(3, 1),
+            Ref::ColumnRange(Coord::relative(1), Coord::relative(3))
+        );
+        assert_eq!(
+            Ref::cell_range((1, 0), (0, 4)),
+            Ref::CellRange(
+                (Coord::relative(0), Coord::relative(0)),
+                (Coord::relative(1), Coord::relative(4))
+            )
+        );
+        assert_eq!(Ref::cell(2, 1).normalize(), Ref::cell(2, 1));
+        let r = Ref::CellRange(
+            (Coord::absolute(1), Coord::relative(0)),
+            (Coord::relative(0), Coord::absolute(0)),
+        );
+        let r = Ref::External(
+            "a.ods".into(),
+            Box::new(r.on_sheet(SheetName::relative("Sheet1"))),
+        );
+        assert_eq!(
+            Expr::Ref(r.normalize()).to_string(),
+            "'a.ods'#Sheet1.A1:$B$1"
+        );
+    }
 }
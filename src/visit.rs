@@ -0,0 +1,300 @@
+//! Traversal of OpenFormula expressions.
+//!
+//! [`Visitor`] and [`VisitorMut`] walk all operands of an expression, their
+//! methods can be overridden to inspect or modify the parts of interest.
+//! [`Expr::fold`] and [`Expr::map`] cover the common cases with closures.
+
+use crate::types::{Expr, Ref};
+
+/// A visitor of expressions.
+///
+/// The default methods visit all operands. Overriding methods can call
+/// [`walk_expr`] to continue with the operands of an expression.
+///
+/// Example
+/// ```rust
+/// use open_formula::{parser::{Parser, parser}, types::*, visit::Visitor};
+///
+/// #[derive(Default)]
+/// struct FunctionNames(Vec<String>);
+///
+/// impl Visitor for FunctionNames {
+///     fn visit_func(&mut self, name: &str, args: &[Expr]) {
+///         self.0.push(name.to_string());
+///         args.iter().for_each(|arg| self.visit_expr(arg));
+///     }
+/// }
+///
+/// let expr = parser().parse("SUM(A1;ABS(B1))*2").unwrap();
+/// let mut names = FunctionNames::default();
+/// names.visit_expr(&expr);
+/// assert_eq!(names.0, vec!["SUM", "ABS"]);
+/// ```
+pub trait Visitor {
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_func(&mut self, _name: &str, args: &[Expr]) {
+        args.iter().for_each(|arg| self.visit_expr(arg));
+    }
+
+    fn visit_name(&mut self, _name: &str) {}
+
+    fn visit_ref(&mut self, _r: &Ref) {}
+}
+
+/// Visits the operands of an expression.
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::Num(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Error(_)
+        | Expr::Missing
+        | Expr::Empty => {}
+        Expr::Perc(a) | Expr::Neg(a) => visitor.visit_expr(a),
+        Expr::Range(a, b)
+        | Expr::RefIntersection(a, b)
+        | Expr::RefUnion(a, b)
+        | Expr::Add(a, b)
+        | Expr::Sub(a, b)
+        | Expr::Mul(a, b)
+        | Expr::Div(a, b)
+        | Expr::Pow(a, b)
+        | Expr::Concat(a, b)
+        | Expr::Cond(_, a, b) => {
+            visitor.visit_expr(a);
+            visitor.visit_expr(b);
+        }
+        Expr::Func(name, args) => visitor.visit_func(name, args),
+        Expr::Name(name) => visitor.visit_name(name),
+        Expr::Ref(r) => visitor.visit_ref(r),
+        Expr::Array(rows) => rows.iter().flatten().for_each(|e| visitor.visit_expr(e)),
+    }
+}
+
+/// A visitor that can modify expressions in place.
+///
+/// The default methods visit all operands. Overriding methods can call
+/// [`walk_expr_mut`] to continue with the operands of an expression.
+///
+/// Example
+/// ```rust
+/// use open_formula::{parser::{Parser, parser}, types::*, visit::VisitorMut};
+///
+/// struct Uppercase;
+///
+/// impl VisitorMut for Uppercase {
+///     fn visit_name_mut(&mut self, name: &mut String) {
+///         *name = name.to_uppercase();
+///     }
+/// }
+///
+/// let mut expr = parser().parse("TaxRate*SUM(Net)").unwrap();
+/// Uppercase.visit_expr_mut(&mut expr);
+/// assert_eq!(expr.to_string(), "TAXRATE*SUM(NET)");
+/// ```
+pub trait VisitorMut {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_func_mut(&mut self, _name: &mut String, args: &mut [Expr]) {
+        args.iter_mut().for_each(|arg| self.visit_expr_mut(arg));
+    }
+
+    fn visit_name_mut(&mut self, _name: &mut String) {}
+
+    fn visit_ref_mut(&mut self, _r: &mut Ref) {}
+}
+
+/// Visits the operands of an expression mutably.
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::Num(_)
+        | Expr::Bool(_)
+        | Expr::String(_)
+        | Expr::Error(_)
+        | Expr::Missing
+        | Expr::Empty => {}
+        Expr::Perc(a) | Expr::Neg(a) => visitor.visit_expr_mut(a),
+        Expr::Range(a, b)
+        | Expr::RefIntersection(a, b)
+        | Expr::RefUnion(a, b)
+        | Expr::Add(a, b)
+        | Expr::Sub(a, b)
+        | Expr::Mul(a, b)
+        | Expr::Div(a, b)
+        | Expr::Pow(a, b)
+        | Expr::Concat(a, b)
+        | Expr::Cond(_, a, b) => {
+            visitor.visit_expr_mut(a);
+            visitor.visit_expr_mut(b);
+        }
+        Expr::Func(name, args) => visitor.visit_func_mut(name, args),
+        Expr::Name(name) => visitor.visit_name_mut(name),
+        Expr::Ref(r) => visitor.visit_ref_mut(r),
+        Expr::Array(rows) => rows
+            .iter_mut()
+            .flatten()
+            .for_each(|e| visitor.visit_expr_mut(e)),
+    }
+}
+
+impl Expr {
+    /// Combines an expression and all of its operands, in the order they
+    /// appear in the formula, with the given function.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::{parser::{Parser, parser}, types::*};
+    /// let expr = parser().parse("1+SUM(2;3*4)").unwrap();
+    /// let sum = expr.fold(0.0, |acc, e| match e {
+    ///     Expr::Num(n) => acc + n,
+    ///     _ => acc,
+    /// });
+    /// assert_eq!(sum, 10.0);
+    /// ```
+    pub fn fold<T, F>(&self, init: T, f: F) -> T
+    where
+        F: FnMut(T, &Expr) -> T,
+    {
+        struct Folder<T, F> {
+            acc: Option<T>,
+            f: F,
+        }
+
+        impl<T, F: FnMut(T, &Expr) -> T> Visitor for Folder<T, F> {
+            fn visit_expr(&mut self, expr: &Expr) {
+                let acc = self.acc.take().expect("accumulator is always set");
+                self.acc = Some((self.f)(acc, expr));
+                walk_expr(self, expr);
+            }
+        }
+
+        let mut folder = Folder { acc: Some(init), f };
+        folder.visit_expr(self);
+        folder.acc.expect("accumulator is always set")
+    }
+
+    /// Transforms an expression bottom-up: all operands are transformed before
+    /// the expression containing them.
+    ///
+    /// Example
+    /// ```rust
+    /// use open_formula::{parser::{Parser, parser}, types::*};
+    /// let expr = parser().parse("-(A1+Sheet2.B1)").unwrap();
+    /// let expr = expr.map(&mut |e| match e {
+    ///     Expr::Ref(_) => Expr::Num(1.0),
+    ///     e => e,
+    /// });
+    /// assert_eq!(expr.to_string(), "-(1+1)");
+    /// ```
+    pub fn map<F>(self, f: &mut F) -> Expr
+    where
+        F: FnMut(Expr) -> Expr,
+    {
+        let mut map = |e: Box<Expr>| Box::new(e.map(f));
+        let expr = match self {
+            Expr::Range(a, b) => Expr::Range(map(a), map(b)),
+            Expr::RefIntersection(a, b) => Expr::RefIntersection(map(a), map(b)),
+            Expr::RefUnion(a, b) => Expr::RefUnion(map(a), map(b)),
+            Expr::Perc(a) => Expr::Perc(map(a)),
+            Expr::Neg(a) => Expr::Neg(map(a)),
+            Expr::Add(a, b) => Expr::Add(map(a), map(b)),
+            Expr::Sub(a, b) => Expr::Sub(map(a), map(b)),
+            Expr::Mul(a, b) => Expr::Mul(map(a), map(b)),
+            Expr::Div(a, b) => Expr::Div(map(a), map(b)),
+            Expr::Pow(a, b) => Expr::Pow(map(a), map(b)),
+            Expr::Concat(a, b) => Expr::Concat(map(a), map(b)),
+            Expr::Cond(c, a, b) => Expr::Cond(c, map(a), map(b)),
+            Expr::Func(name, args) => {
+                Expr::Func(name, args.into_iter().map(|e| e.map(f)).collect())
+            }
+            Expr::Array(rows) => Expr::Array(
+                rows.into_iter()
+                    .map(|row| row.into_iter().map(|e| e.map(f)).collect())
+                    .collect(),
+            ),
+            expr @ (Expr::Num(_)
+            | Expr::Bool(_)
+            | Expr::String(_)
+            | Expr::Name(_)
+            | Expr::Ref(_)
+            | Expr::Error(_)
+            | Expr::Missing
+            | Expr::Empty) => expr,
+        };
+        f(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parser, Parser};
+
+    fn parse(input: &str) -> Expr {
+        parser().parse(input).unwrap()
+    }
+
+    #[test]
+    fn refs() {
+        let expr = parse("A1-B1*C1/D1^E1&F1=(G1:H1!I1~J1)%+SUM(K1;{1})+Sheet2.L1");
+        let refs: Vec<_> = expr.refs().collect();
+        assert_eq!(refs.len(), 11);
+        assert_eq!(refs[0], Ref::cell(0, 0));
+        assert_eq!(refs[6], Ref::cell_range((6, 0), (7, 0)));
+        assert_eq!(refs[9], Ref::cell(10, 0));
+        assert!(parse("1+\"A1\"").refs().next().is_none());
+    }
+
+    #[test]
+    fn fold() {
+        let expr = parse("IF(A1>0;-A1;{1;2})");
+        let count = expr.fold(0, |n, _| n + 1);
+        assert_eq!(count, 9);
+        let names = parse("SUM(TaxRate;Net)").fold(vec![], |mut names, e| {
+            if let Expr::Name(name) = e {
+                names.push(name.clone());
+            }
+            names
+        });
+        assert_eq!(names, vec!["TaxRate", "Net"]);
+    }
+
+    #[test]
+    fn map() {
+        let expr = parse("1+2*SUM(3;{4})").map(&mut |e| match e {
+            Expr::Num(n) => Expr::Num(n * 10.0),
+            e => e,
+        });
+        assert_eq!(expr, parse("10+20*SUM(30;{40})"));
+        // operands are transformed first
+        let mut order = vec![];
+        parse("-(1+2)").map(&mut |e| {
+            order.push(e.to_string());
+            e
+        });
+        assert_eq!(order, vec!["1", "2", "1+2", "-(1+2)"]);
+    }
+
+    #[test]
+    fn visitor_mut() {
+        struct Absolute;
+
+        impl VisitorMut for Absolute {
+            fn visit_ref_mut(&mut self, r: &mut Ref) {
+                if let Ref::CellRef(col, row) = r {
+                    col.abs = true;
+                    row.abs = true;
+                }
+            }
+        }
+
+        let mut expr = parse("A1+ABS(B2*-C3)");
+        Absolute.visit_expr_mut(&mut expr);
+        assert_eq!(expr, parse("$A$1+ABS($B$2*-$C$3)"));
+    }
+}